@@ -0,0 +1,230 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::iter::Peekable;
+
+use anyhow::Context;
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::{abs_difference, add_similarity, parse_line, Location, ParseOptions};
+
+/// Most runs merged at once. Both columns are merged together, so
+/// at most twice this many run files are ever open.
+const MAX_FAN_IN: usize = 64;
+
+/// One column of the input, sorted in runs of at most `chunk_len`
+/// values, each run in its own temporary file. The runs are kept as
+/// paths so they don't hold a file open until they're merged.
+struct Column<T> {
+    chunk_len: usize,
+    buffer: Vec<T>,
+    runs: Vec<TempPath>,
+}
+
+/// Writes sorted `values` out as a new run
+fn write_run<T: Location>(values: impl Iterator<Item = anyhow::Result<T>>) -> anyhow::Result<TempPath> {
+    let mut writer = BufWriter::new(NamedTempFile::new()?);
+    for value in values {
+        writeln!(writer, "{}", value?)?;
+    }
+
+    Ok(writer.into_inner()?.into_temp_path())
+}
+
+impl<T: Location> Column<T> {
+    fn new(chunk_len: usize) -> Self {
+        Column {
+            chunk_len,
+            buffer: Vec::with_capacity(chunk_len),
+            runs: vec![],
+        }
+    }
+
+    fn push(&mut self, value: T) -> anyhow::Result<()> {
+        self.buffer.push(value);
+        if self.buffer.len() >= self.chunk_len {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the buffered values and moves them out to a new run
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        self.buffer.sort();
+        let run = write_run(self.buffer.drain(..).map(Ok))?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// Every value of the column in order. Runs are first merged
+    /// `MAX_FAN_IN` at a time until there are few enough to merge
+    /// in one go.
+    fn sorted(&mut self) -> anyhow::Result<Merge<T>> {
+        self.flush()?;
+
+        while self.runs.len() > MAX_FAN_IN {
+            let group = self.runs.drain(..MAX_FAN_IN).collect::<Vec<_>>();
+            let merged = write_run(Merge::<T>::open(&group)?)?;
+            self.runs.push(merged);
+        }
+
+        Merge::open(&self.runs)
+    }
+}
+
+/// K-way merge of sorted runs, holding one value per run in memory
+struct Merge<T> {
+    readers: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+}
+
+impl<T: Location> Merge<T> {
+    fn open(runs: &[TempPath]) -> anyhow::Result<Self> {
+        let mut merge = Merge {
+            readers: vec![],
+            heap: BinaryHeap::new(),
+        };
+        for (i, run) in runs.iter().enumerate() {
+            merge.readers.push(BufReader::new(File::open(run)?).lines());
+            merge.refill(i)?;
+        }
+
+        Ok(merge)
+    }
+
+    /// Puts the next value of `run` on the heap, if there is one
+    fn refill(&mut self, run: usize) -> anyhow::Result<()> {
+        if let Some(line) = self.readers[run].next() {
+            let value = line?.parse::<T>().context("corrupted sort run")?;
+            self.heap.push(Reverse((value, run)));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Location> Iterator for Merge<T> {
+    type Item = anyhow::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((value, run)) = self.heap.pop()?;
+        Some(self.refill(run).map(|_| value))
+    }
+}
+
+/// The next value of a sorted stream and how many times in a row it appears
+fn next_run<T: Location>(values: &mut Peekable<Merge<T>>) -> anyhow::Result<Option<(T, usize)>> {
+    let value = match values.next() {
+        Some(value) => value?,
+        None => return Ok(None),
+    };
+
+    let mut count = 1;
+    while let Some(Ok(next)) = values.peek() {
+        if *next != value {
+            break;
+        }
+        values.next();
+        count += 1;
+    }
+
+    Ok(Some((value, count)))
+}
+
+/// Part one over the merged columns
+fn difference<T: Location>(one: Merge<T>, two: Merge<T>) -> anyhow::Result<T> {
+    let mut sum = T::zero();
+    for (a, b) in one.zip(two) {
+        let (a, b) = (a?, b?);
+        sum = abs_difference(a, b)
+            .and_then(|d| sum.checked_add(&d))
+            .with_context(|| format!("overflow adding the difference between {} and {}", a, b))?;
+    }
+
+    Ok(sum)
+}
+
+/// Part two over the merged columns, walking runs of equal values
+/// in both like `calculate_similarity_sorted`
+fn similarity<T: Location>(one: Merge<T>, two: Merge<T>) -> anyhow::Result<T> {
+    let (mut one, mut two) = (one.peekable(), two.peekable());
+
+    let mut similarity = T::zero();
+    let mut run_one = next_run(&mut one)?;
+    let mut run_two = next_run(&mut two)?;
+    while let (Some((a, count_one)), Some((b, count_two))) = (run_one, run_two) {
+        if a < b {
+            run_one = next_run(&mut one)?;
+        } else if a > b {
+            run_two = next_run(&mut two)?;
+        } else {
+            let count = count_one
+                .checked_mul(count_two)
+                .context("overflow counting matching location IDs")?;
+            similarity = add_similarity(similarity, a, count)?;
+            run_one = next_run(&mut one)?;
+            run_two = next_run(&mut two)?;
+        }
+    }
+
+    Ok(similarity)
+}
+
+/// Solves both parts without holding the lists in memory: each
+/// column is sorted in chunks of `chunk_len` values on disk, then
+/// the merged columns are streamed through the computations.
+pub fn solve_external<T: Location, R: BufRead>(
+    reader: R,
+    options: &ParseOptions,
+    chunk_len: usize,
+) -> anyhow::Result<(T, T)> {
+    let mut one = Column::<T>::new(chunk_len);
+    let mut two = Column::<T>::new(chunk_len);
+    for (i, text) in reader.lines().enumerate() {
+        if let Some((first_elem, second_elem)) = parse_line(&text?, i + 1, options)? {
+            one.push(first_elem)?;
+            two.push(second_elem)?;
+        }
+    }
+
+    let difference = difference(one.sorted()?, two.sorted()?)?;
+    let similarity = similarity(one.sorted()?, two.sorted()?)?;
+    Ok((difference, similarity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solve;
+
+    #[test]
+    fn matches_in_memory_solve() {
+        let input = include_str!("input.in");
+        let options = ParseOptions::default();
+        let expected = solve::<i64>(input, &options).unwrap();
+
+        // Small chunks make far more runs than MAX_FAN_IN, so these
+        // also go through several merge passes
+        for chunk_len in [1, 2, 3, 7, 64, 1000, 5000] {
+            let found = solve_external::<i64, _>(input.as_bytes(), &options, chunk_len).unwrap();
+            assert_eq!(found, expected, "chunk size {}", chunk_len);
+        }
+    }
+
+    #[test]
+    fn matches_in_memory_solve_with_repeats() {
+        let input = "3   4\n4   3\n2   5\n1   3\n3   9\n3   3\n";
+        let options = ParseOptions::default();
+        let expected = solve::<i64>(input, &options).unwrap();
+        assert_eq!(expected, (11, 31));
+
+        for chunk_len in 1..=7 {
+            let found = solve_external::<i64, _>(input.as_bytes(), &options, chunk_len).unwrap();
+            assert_eq!(found, expected, "chunk size {}", chunk_len);
+        }
+    }
+}
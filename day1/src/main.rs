@@ -1,39 +1,126 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
 
-pub fn read_input(input_text: &str) -> (Vec<i32>, Vec<i32>) {
+use anyhow::{bail, Context};
+use num_traits::PrimInt;
 
-    let mut list_one: Vec<i32> = vec![];
-    let mut list_two: Vec<i32> = vec![];
+mod external;
+mod metrics;
 
-    for line in input_text.lines()  {
-        // split on spaces
-        let split_text = line.split(' ');
-        assert!(split_text.clone().count() > 2);
+/// The location ID types the lists can be read as, e.g. `i64` or `i128`
+pub trait Location: PrimInt + Hash + FromStr<Err: Error + Send + Sync + 'static> + Display + Debug {}
 
-        let first_elem = split_text.clone().next().unwrap();
-        let second_elem = split_text.last().unwrap();
+impl<T: PrimInt + Hash + FromStr<Err: Error + Send + Sync + 'static> + Display + Debug> Location for T {}
 
-        list_one.push(first_elem.parse::<i32>().unwrap());
-        list_two.push(second_elem.parse::<i32>().unwrap());
+/// How the two columns of the input are separated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Any mix of whitespace, commas and tabs
+    Loose,
+    /// Commas, with optional spaces around the fields
+    Csv,
+    /// Tabs, with optional spaces around the fields
+    Tsv,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ParseOptions {
+    pub format: Format,
+    /// Whether the first line names the columns (CSV only)
+    pub header: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            format: Format::Loose,
+            header: false,
+        }
+    }
+}
+
+/// Splits a line of the input into its fields
+fn fields(text: &str, format: Format) -> Vec<&str> {
+    match format {
+        Format::Loose => text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|field| !field.is_empty())
+            .collect(),
+        Format::Csv => text.split(',').map(str::trim).collect(),
+        Format::Tsv => text.split('\t').map(str::trim).collect(),
+    }
+}
+
+/// The pair of location IDs on `line` (1-based) of the input, or
+/// `None` for blank lines and the header
+pub fn parse_line<T: Location>(text: &str, line: usize, options: &ParseOptions) -> anyhow::Result<Option<(T, T)>> {
+    if text.trim().is_empty() {
+        return Ok(None);
     }
 
-    (list_one, list_two)
+    let fields = fields(text, options.format);
+    if fields.len() != 2 {
+        bail!("line {}: expected 2 columns, found {}", line, fields.len())
+    }
+
+    if options.header && line == 1 {
+        return Ok(None);
+    }
+
+    let parse = |field: &str| {
+        field
+            .parse::<T>()
+            .with_context(|| format!("line {}: invalid location ID `{}`", line, field))
+    };
+    Ok(Some((parse(fields[0])?, parse(fields[1])?)))
+}
+
+pub fn read_input<T: Location>(input_text: &str, options: &ParseOptions) -> anyhow::Result<(Vec<T>, Vec<T>)> {
+
+    let mut list_one: Vec<T> = vec![];
+    let mut list_two: Vec<T> = vec![];
+
+    for (i, text) in input_text.lines().enumerate() {
+        if let Some((first_elem, second_elem)) = parse_line(text, i + 1, options)? {
+            list_one.push(first_elem);
+            list_two.push(second_elem);
+        }
+    }
+
+    Ok((list_one, list_two))
+}
+
+/// `|a - b|`, or `None` if it doesn't fit in `T`
+pub fn abs_difference<T: Location>(a: T, b: T) -> Option<T> {
+    if a >= b {
+        a.checked_sub(&b)
+    } else {
+        b.checked_sub(&a)
+    }
 }
 
-pub fn calculate_difference(list_one: &mut [i32], list_two: &mut Vec<i32>) -> u32 {
+pub fn calculate_difference<T: Location>(list_one: &mut [T], list_two: &mut [T]) -> anyhow::Result<T> {
     list_one.sort();
     list_two.sort();
 
     list_one
         .iter()
-        .zip(list_two)
-        .map(|(&a, &mut b)| (a - b).unsigned_abs())
-        .sum()
+        .zip(list_two.iter())
+        .try_fold(T::zero(), |sum, (&a, &b)| {
+            abs_difference(a, b)
+                .and_then(|d| sum.checked_add(&d))
+                .with_context(|| format!("overflow adding the difference between {} and {}", a, b))
+        })
 }
 
-pub fn elem_freq(list: &[i32]) -> HashMap<i32, u32> {
+pub fn elem_freq<T: Location>(list: &[T]) -> HashMap<T, usize> {
 
-    let mut frequencies =  HashMap::<i32, u32>::new();
+    let mut frequencies =  HashMap::<T, usize>::new();
     for elem in list {
         match frequencies.get_mut(elem) {
             Some(v) => {
@@ -48,33 +135,246 @@ pub fn elem_freq(list: &[i32]) -> HashMap<i32, u32> {
     frequencies
 }
 
+/// Adds `elem * count` to `similarity`, failing on overflow
+pub fn add_similarity<T: Location>(similarity: T, elem: T, count: usize) -> anyhow::Result<T> {
+    T::from(count)
+        .and_then(|count| elem.checked_mul(&count))
+        .and_then(|product| similarity.checked_add(&product))
+        .with_context(|| format!("overflow adding {} appearing {} times to the similarity", elem, count))
+}
 
-pub fn calculate_similarity(list_one: &[i32], list_two: &[i32]) -> u32 {
+pub fn calculate_similarity<T: Location>(list_one: &[T], list_two: &[T]) -> anyhow::Result<T> {
 
     let freq_l2 = elem_freq(list_two);
 
-    let mut similarity: u32 = 0;
+    let mut similarity = T::zero();
     for elem in list_one {
-        
+
         let multiplier = freq_l2
             .get(elem)
             .cloned()
             .unwrap_or(0);
 
-        similarity += multiplier * (*elem as u32);
+        similarity = add_similarity(similarity, *elem, multiplier)?;
     }
 
-    similarity
+    Ok(similarity)
 }
 
+/// Same as `calculate_similarity`, but walks both lists together
+/// instead of counting into a `HashMap`. Both lists must be sorted,
+/// as `calculate_difference` leaves them.
+pub fn calculate_similarity_sorted<T: Location>(list_one: &[T], list_two: &[T]) -> anyhow::Result<T> {
+    let mut similarity = T::zero();
+    let (mut i, mut j) = (0, 0);
+    while i < list_one.len() && j < list_two.len() {
+        let elem = list_one[i];
+        if elem < list_two[j] {
+            i += 1;
+        } else if elem > list_two[j] {
+            j += 1;
+        } else {
+            // Every copy of elem in list one picks up every copy in list two
+            let run_one = list_one[i..].iter().take_while(|&&e| e == elem).count();
+            let run_two = list_two[j..].iter().take_while(|&&e| e == elem).count();
+            let count = run_one
+                .checked_mul(run_two)
+                .context("overflow counting matching location IDs")?;
 
-fn main() {
+            similarity = add_similarity(similarity, elem, count)?;
+            i += run_one;
+            j += run_two;
+        }
+    }
+
+    Ok(similarity)
+}
+
+fn solve<T: Location>(input: &str, options: &ParseOptions) -> anyhow::Result<(T, T)> {
     // Part one : get the list differences sum
-    let (mut list_one, mut list_two) = read_input(include_str!("input.in"));
-    let part_one_res= calculate_difference(&mut list_one, &mut list_two);
-    println!("part one: {}", part_one_res);
+    let (mut list_one, mut list_two) = read_input::<T>(input, options)?;
+    let part_one_res = calculate_difference(&mut list_one, &mut list_two)?;
+
+    // Part two: get the similarity. The lists are sorted now, so
+    // no need for the frequency map
+    let part_two_res = calculate_similarity_sorted(&list_one, &list_two)?;
+
+    Ok((part_one_res, part_two_res))
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> anyhow::Result<Option<&'a String>> {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => Ok(Some(args.get(i + 1).with_context(|| format!("{} needs a value", flag))?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads `--csv`, `--tsv` and `--header` (for CSV files that start
+/// with the column names)
+fn parse_options_from_args(args: &[String]) -> anyhow::Result<ParseOptions> {
+    let has = |flag: &str| args.iter().any(|a| a == flag);
+
+    let format = match (has("--csv"), has("--tsv")) {
+        (false, false) => Format::Loose,
+        (true, false) => Format::Csv,
+        (false, true) => Format::Tsv,
+        (true, true) => bail!("--csv and --tsv can't be used together"),
+    };
+
+    let header = has("--header");
+    if header && format != Format::Csv {
+        bail!("--header only applies to --csv")
+    }
+
+    Ok(ParseOptions { format, header })
+}
 
-    // Part two: get the similarity
-    let part_two_res = calculate_similarity(&list_one, &list_two);
+fn run<T: Location>(args: &[String]) -> anyhow::Result<()> {
+    let options = parse_options_from_args(args)?;
+
+    // Pass `--input <file>` to read other lists
+    let path = flag_value(args, "--input")?;
+
+    // Pass `--external` to sort the lists on disk, `--chunk-size <n>`
+    // values per column at a time
+    let external = args.iter().any(|a| a == "--external");
+
+    // Pass `--metric <a,b,...>` for other comparisons of the lists,
+    // out of jaccard, intersection, emd, kendall, median and p<n>
+    let metrics = match flag_value(args, "--metric")? {
+        Some(names) => names
+            .split(',')
+            .map(str::parse::<metrics::Metric>)
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => vec![],
+    };
+    if external && !metrics.is_empty() {
+        bail!("--metric needs the lists in memory, so can't be used with --external")
+    }
+
+    let (part_one_res, part_two_res) = if external {
+        let chunk_len = match flag_value(args, "--chunk-size")? {
+            Some(n) => n.parse::<usize>()?,
+            None => 1 << 20,
+        };
+        if chunk_len == 0 {
+            bail!("--chunk-size must be at least 1")
+        }
+
+        match path {
+            Some(path) => external::solve_external::<T, _>(BufReader::new(File::open(path)?), &options, chunk_len)?,
+            None => external::solve_external::<T, _>(include_str!("input.in").as_bytes(), &options, chunk_len)?,
+        }
+    } else {
+        match path {
+            Some(path) => solve::<T>(&std::fs::read_to_string(path)?, &options)?,
+            None => solve::<T>(include_str!("input.in"), &options)?,
+        }
+    };
+
+    println!("part one: {}", part_one_res);
     println!("part two: {}", part_two_res);
+
+    if !metrics.is_empty() {
+        let input = match path {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => include_str!("input.in").to_string(),
+        };
+        let (list_one, list_two) = read_input::<T>(&input, &options)?;
+        for metric in metrics {
+            println!("{}: {}", metric, metrics::evaluate(metric, &list_one, &list_two)?);
+        }
+    }
+    Ok(())
+}
+
+fn try_main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+
+    // Pass `--i128` for lists with IDs or sums too big for `i64`
+    if args.iter().any(|a| a == "--i128") {
+        run::<i128>(&args)
+    } else {
+        run::<i64>(&args)
+    }
+}
+
+fn main() {
+    match try_main() {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("finished with error: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    /// Small IDs, so the lists share plenty of them
+    fn small_list(ids: &[i8]) -> Vec<i64> {
+        ids.iter().map(|&id| (id % 8) as i64).collect()
+    }
+
+    #[quickcheck]
+    fn sorted_similarity_matches_hash_map(one: Vec<i8>, two: Vec<i8>) -> bool {
+        let (mut one, mut two) = (small_list(&one), small_list(&two));
+        one.sort();
+        two.sort();
+
+        calculate_similarity_sorted(&one, &two).unwrap() == calculate_similarity(&one, &two).unwrap()
+    }
+
+    fn parse(input: &str, format: Format, header: bool) -> anyhow::Result<(Vec<i64>, Vec<i64>)> {
+        read_input(input, &ParseOptions { format, header })
+    }
+
+    #[test]
+    fn loose_fields() {
+        assert_eq!(fields("1\t2", Format::Loose), ["1", "2"]);
+        assert_eq!(fields("1 2", Format::Loose), ["1", "2"]);
+        assert_eq!(fields(" 1 ,\t 2 ", Format::Loose), ["1", "2"]);
+        assert_eq!(fields("1,,2", Format::Loose), ["1", "2"]);
+
+        let lists = parse("3   4\n4,3\n\n2\t5\n", Format::Loose, false).unwrap();
+        assert_eq!(lists, (vec![3, 4, 2], vec![4, 3, 5]));
+    }
+
+    #[test]
+    fn csv_and_tsv() {
+        let lists = parse("left,right\n1, 2\n 3 ,4\n", Format::Csv, true).unwrap();
+        assert_eq!(lists, (vec![1, 3], vec![2, 4]));
+
+        // Without `--header` the column names aren't IDs
+        let error = format!("{:#}", parse("left,right\n1,2\n", Format::Csv, false).unwrap_err());
+        assert!(error.starts_with("line 1: invalid location ID `left`"), "{}", error);
+
+        assert_eq!(fields(" 1 \t 2", Format::Tsv), ["1", "2"]);
+        assert_eq!(parse("1 \t 2\n", Format::Tsv, false).unwrap(), (vec![1], vec![2]));
+
+        // Commas aren't separators in TSV files, or spaces in CSV ones
+        assert!(parse("1,\t2\n", Format::Tsv, false).is_err());
+        assert!(parse("1 2\n", Format::Csv, false).is_err());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = format!("{:#}", parse("1 2\n3 4 5\n", Format::Loose, false).unwrap_err());
+        assert_eq!(error, "line 2: expected 2 columns, found 3");
+
+        let error = format!("{:#}", parse("1 2\n\n3 x\n", Format::Loose, false).unwrap_err());
+        assert!(error.starts_with("line 3: invalid location ID `x`"), "{}", error);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let (mut one, mut two) = (vec![i64::MIN, 0], vec![i64::MAX, 0]);
+        assert!(calculate_difference(&mut one, &mut two).is_err());
+
+        let (one, two) = (vec![i64::MAX; 2], vec![i64::MAX; 2]);
+        assert!(calculate_similarity_sorted(&one, &two).is_err());
+        assert!(calculate_similarity(&one, &two).is_err());
+    }
 }
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::{elem_freq, Location};
+
+/// Ways to compare the two lists besides the puzzle's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    /// Shared distinct IDs over all distinct IDs
+    Jaccard,
+    /// How many IDs the lists share, counting repeats
+    Intersection,
+    /// Earth mover's distance between the distributions of the IDs
+    EarthMovers,
+    /// Kendall's tau-b between the two columns, row by row
+    KendallTau,
+    /// Median of list one minus median of list two
+    Median,
+    /// The given percentile of list one minus that of list two
+    Percentile(f64),
+}
+
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    /// Reads `jaccard`, `intersection`, `emd`, `kendall`, `median`
+    /// or `p<percentile>`, e.g. `p90`
+    fn from_str(name: &str) -> anyhow::Result<Metric> {
+        Ok(match name {
+            "jaccard" => Metric::Jaccard,
+            "intersection" => Metric::Intersection,
+            "emd" => Metric::EarthMovers,
+            "kendall" => Metric::KendallTau,
+            "median" => Metric::Median,
+            _ => match name.strip_prefix('p').map(str::parse::<f64>) {
+                Some(Ok(p)) if (0.0..=100.0).contains(&p) => Metric::Percentile(p),
+                _ => bail!("unknown metric `{}`", name),
+            },
+        })
+    }
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Jaccard => write!(f, "jaccard"),
+            Metric::Intersection => write!(f, "intersection"),
+            Metric::EarthMovers => write!(f, "emd"),
+            Metric::KendallTau => write!(f, "kendall"),
+            Metric::Median => write!(f, "median"),
+            Metric::Percentile(p) => write!(f, "p{}", p),
+        }
+    }
+}
+
+fn to_f64<T: Location>(value: T) -> f64 {
+    value.to_f64().unwrap()
+}
+
+/// Jaccard index of the sets of distinct IDs in each list
+pub fn jaccard<T: Location>(list_one: &[T], list_two: &[T]) -> anyhow::Result<f64> {
+    let one = list_one.iter().collect::<HashSet<_>>();
+    let two = list_two.iter().collect::<HashSet<_>>();
+
+    let union = one.union(&two).count();
+    if union == 0 {
+        bail!("jaccard index of two empty lists")
+    }
+
+    Ok(one.intersection(&two).count() as f64 / union as f64)
+}
+
+/// Size of the multiset intersection: each ID counts as many
+/// times as it appears in the list with fewer copies of it
+pub fn intersection<T: Location>(list_one: &[T], list_two: &[T]) -> usize {
+    let freq_l2 = elem_freq(list_two);
+    elem_freq(list_one)
+        .iter()
+        .map(|(elem, &count)| count.min(freq_l2.get(elem).cloned().unwrap_or(0)))
+        .sum()
+}
+
+/// Earth mover's distance between the distributions of IDs in the
+/// two lists: the area between their cumulative distributions. For
+/// lists of the same length this is the part one sum over the length.
+pub fn earth_movers<T: Location>(list_one: &[T], list_two: &[T]) -> anyhow::Result<f64> {
+    if list_one.is_empty() || list_two.is_empty() {
+        bail!("earth mover's distance needs two non empty lists")
+    }
+
+    let freq_l1 = elem_freq(list_one);
+    let freq_l2 = elem_freq(list_two);
+
+    let mut values = freq_l1.keys().chain(freq_l2.keys()).copied().collect::<Vec<_>>();
+    values.sort();
+    values.dedup();
+
+    let (len_one, len_two) = (list_one.len() as f64, list_two.len() as f64);
+    let (mut below_one, mut below_two) = (0, 0);
+    let mut distance = 0.0;
+    for (i, &value) in values.iter().enumerate() {
+        below_one += freq_l1.get(&value).cloned().unwrap_or(0);
+        below_two += freq_l2.get(&value).cloned().unwrap_or(0);
+
+        if let Some(&next) = values.get(i + 1) {
+            let gap = to_f64(next) - to_f64(value);
+            distance += (below_one as f64 / len_one - below_two as f64 / len_two).abs() * gap;
+        }
+    }
+
+    Ok(distance)
+}
+
+/// Number of pairs among `count` equal values
+fn tied_pairs(count: usize) -> usize {
+    count * count.saturating_sub(1) / 2
+}
+
+/// Kendall's tau-b between the columns, taking each line of the
+/// input as one observation. This compares every pair of lines, so
+/// it's quadratic in the length of the lists.
+pub fn kendall_tau<T: Location>(list_one: &[T], list_two: &[T]) -> anyhow::Result<f64> {
+    if list_one.len() != list_two.len() {
+        bail!("kendall tau needs lists of the same length")
+    }
+
+    let (mut concordant, mut discordant) = (0usize, 0usize);
+    for i in 0..list_one.len() {
+        for j in i + 1..list_one.len() {
+            let order_one = list_one[i].cmp(&list_one[j]);
+            let order_two = list_two[i].cmp(&list_two[j]);
+            if order_one.is_eq() || order_two.is_eq() {
+                continue;
+            }
+
+            if order_one == order_two {
+                concordant += 1;
+            } else {
+                discordant += 1;
+            }
+        }
+    }
+
+    let pairs = tied_pairs(list_one.len());
+    let ties_one = elem_freq(list_one).values().map(|&c| tied_pairs(c)).sum::<usize>();
+    let ties_two = elem_freq(list_two).values().map(|&c| tied_pairs(c)).sum::<usize>();
+    let denominator = ((pairs - ties_one) as f64 * (pairs - ties_two) as f64).sqrt();
+    if denominator == 0.0 {
+        bail!("kendall tau is undefined when a column has a single distinct value")
+    }
+
+    Ok((concordant as f64 - discordant as f64) / denominator)
+}
+
+/// The `p`th percentile of `list`, interpolating linearly between
+/// the two closest IDs
+pub fn percentile<T: Location>(list: &[T], p: f64) -> anyhow::Result<f64> {
+    if list.is_empty() {
+        bail!("percentile of an empty list")
+    }
+
+    let mut sorted = list.to_vec();
+    sorted.sort();
+
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    let (low_value, high_value) = (to_f64(sorted[low]), to_f64(sorted[high]));
+    Ok(low_value + (high_value - low_value) * (rank - low as f64))
+}
+
+/// Computes `metric` over the two lists, in the order they were read
+pub fn evaluate<T: Location>(metric: Metric, list_one: &[T], list_two: &[T]) -> anyhow::Result<f64> {
+    match metric {
+        Metric::Jaccard => jaccard(list_one, list_two),
+        Metric::Intersection => Ok(intersection(list_one, list_two) as f64),
+        Metric::EarthMovers => earth_movers(list_one, list_two),
+        Metric::KendallTau => kendall_tau(list_one, list_two),
+        Metric::Median => Ok(percentile(list_one, 50.0)? - percentile(list_two, 50.0)?),
+        Metric::Percentile(p) => Ok(percentile(list_one, p)? - percentile(list_two, p)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn jaccard_index() {
+        assert_eq!(jaccard(&[1, 2, 3, 3], &[2, 3, 4]).unwrap(), 0.5);
+        assert_eq!(jaccard(&[1], &[]).unwrap(), 0.0);
+        assert!(jaccard::<i64>(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn multiset_intersection() {
+        assert_eq!(intersection(&[1, 1, 2, 3], &[1, 2, 2, 4]), 2);
+        assert_eq!(intersection(&[5, 5, 5], &[5, 5]), 2);
+        assert_eq!(intersection::<i64>(&[], &[1]), 0);
+    }
+
+    #[test]
+    fn earth_movers_distance() {
+        assert_eq!(earth_movers(&[1, 2], &[3, 4]).unwrap(), 2.0);
+        assert_eq!(earth_movers(&[3, 3], &[3]).unwrap(), 0.0);
+        // Half of list two has to move from 2 to 0
+        assert_eq!(earth_movers(&[0], &[0, 2]).unwrap(), 1.0);
+        assert!(earth_movers(&[1], &[]).is_err());
+    }
+
+    #[quickcheck]
+    fn earth_movers_is_mean_difference(pairs: Vec<(i8, i8)>) -> bool {
+        if pairs.is_empty() {
+            return true;
+        }
+
+        let (mut one, mut two): (Vec<i64>, Vec<i64>) = pairs.iter().map(|&(a, b)| (a as i64, b as i64)).unzip();
+        let emd = earth_movers(&one, &two).unwrap();
+        let difference = crate::calculate_difference(&mut one, &mut two).unwrap();
+
+        close(emd, difference as f64 / one.len() as f64)
+    }
+
+    #[test]
+    fn kendall_tau_b() {
+        assert_eq!(kendall_tau(&[1, 2, 3], &[10, 20, 30]).unwrap(), 1.0);
+        assert_eq!(kendall_tau(&[1, 2, 3], &[30, 20, 10]).unwrap(), -1.0);
+
+        // The tied pair of 2s counts as neither, leaving 5 of the 6
+        // pairs concordant, over sqrt((6 - 1) * (6 - 0))
+        assert!(close(kendall_tau(&[1, 2, 2, 3], &[1, 2, 3, 4]).unwrap(), 5.0 / 30f64.sqrt()));
+
+        assert!(kendall_tau(&[1, 1, 1], &[1, 2, 3]).is_err());
+        assert!(kendall_tau(&[1, 2], &[1]).is_err());
+    }
+
+    #[test]
+    fn percentiles() {
+        let list = [4, 1, 3, 2];
+        assert_eq!(percentile(&list, 0.0).unwrap(), 1.0);
+        assert_eq!(percentile(&list, 25.0).unwrap(), 1.75);
+        assert_eq!(percentile(&list, 50.0).unwrap(), 2.5);
+        assert_eq!(percentile(&list, 100.0).unwrap(), 4.0);
+        assert_eq!(percentile(&[7], 90.0).unwrap(), 7.0);
+        assert!(percentile::<i64>(&[], 50.0).is_err());
+
+        assert_eq!(evaluate(Metric::Median, &[1, 2, 3], &[1, 2]).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn metric_names() {
+        for name in ["jaccard", "intersection", "emd", "kendall", "median", "p90", "p12.5"] {
+            assert_eq!(name.parse::<Metric>().unwrap().to_string(), name);
+        }
+        assert!("p101".parse::<Metric>().is_err());
+        assert!("mean".parse::<Metric>().is_err());
+    }
+}
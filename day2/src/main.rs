@@ -1,55 +1,749 @@
-pub fn read_input(input: &str) -> Vec<Vec<i32>> {
-    input
-        .lines()
-        .map(|line| line.split_whitespace())
-        .map(|vec| vec.map(|e| e.parse::<i32>().unwrap()))
-        .map(|vec| vec.collect::<Vec<i32>>())
-        .collect::<Vec<_>>()
+use anyhow::{bail, Context};
+
+/// A report along with the (1-based) line it was read from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub line: usize,
+    pub levels: Vec<i32>,
 }
 
-// Part one: all steps must be safe
-pub fn is_report_safe(report: &[i32]) -> bool {
-    let diff = report
+/// What to do with a report too short to have any steps. Such
+/// reports are trivially safe, which is rarely what's wanted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShortReports {
+    /// Fail to parse the input
+    Reject,
+    /// Leave the report out
+    Skip,
+    /// Keep the report, counting it as safe
+    Keep,
+}
+
+/// How `read_input` treats unusual lines. Lines starting with
+/// `#` are always comments.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Blank lines, i.e. reports with no levels
+    pub empty: ShortReports,
+    /// Reports with a single level
+    pub single: ShortReports,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            empty: ShortReports::Reject,
+            single: ShortReports::Keep,
+        }
+    }
+}
+
+/// Splits `line` on whitespace, keeping the byte offset of each token
+fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push((s, &line[s..i]));
+                start = None;
+            },
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+pub fn read_input(input: &str, options: &ParseOptions) -> anyhow::Result<Vec<Report>> {
+    let mut reports = vec![];
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        if text.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let levels = tokens(text)
+            .into_iter()
+            .enumerate()
+            .map(|(token, (offset, level))| {
+                level.parse::<i32>().with_context(|| {
+                    format!(
+                        "line {}, token {} (column {}): invalid level `{}`",
+                        line,
+                        token + 1,
+                        offset + 1,
+                        level
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let handling = match levels.len() {
+            0 => options.empty,
+            1 => options.single,
+            _ => ShortReports::Keep,
+        };
+
+        match handling {
+            ShortReports::Reject if levels.is_empty() => bail!("line {}: empty report", line),
+            ShortReports::Reject => bail!("line {}: report with a single level", line),
+            ShortReports::Skip => {},
+            ShortReports::Keep => reports.push(Report { line, levels }),
+        }
+    }
+
+    Ok(reports)
+}
+
+/// Which way the levels of a safe report are allowed to go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Monotonicity {
+    Increasing,
+    Decreasing,
+    /// All increasing or all decreasing
+    Either,
+    /// Any mix of increasing and decreasing steps
+    None,
+}
+
+/// What makes a step between two levels safe
+#[derive(Clone, Debug)]
+pub struct SafetyPolicy {
+    /// Smallest allowed change between neighbouring levels
+    pub min_step: i32,
+    /// Largest allowed change between neighbouring levels
+    pub max_step: i32,
+    /// Whether neighbouring levels may be equal, regardless of
+    /// `min_step` (even a `min_step` of 0)
+    pub allow_equal: bool,
+    pub monotonicity: Monotonicity,
+}
+
+impl Default for SafetyPolicy {
+    /// The puzzle's rules: levels all increase or all decrease,
+    /// by 1 to 3 at a time
+    fn default() -> Self {
+        SafetyPolicy {
+            min_step: 1,
+            max_step: 3,
+            allow_equal: false,
+            monotonicity: Monotonicity::Either,
+        }
+    }
+}
+
+/// The direction a whole report is checked against. `Either`
+/// reports are checked against both `Up` and `Down`.
+#[derive(Clone, Copy, Debug)]
+enum Direction {
+    Up,
+    Down,
+    Any,
+}
+
+/// The change from level `a` to level `b`, widened so that it
+/// can't overflow for any pair of `i32` levels
+fn step(a: i32, b: i32) -> i64 {
+    i64::from(b) - i64::from(a)
+}
+
+impl SafetyPolicy {
+    fn directions(&self) -> &'static [Direction] {
+        match self.monotonicity {
+            Monotonicity::Increasing => &[Direction::Up],
+            Monotonicity::Decreasing => &[Direction::Down],
+            Monotonicity::Either => &[Direction::Up, Direction::Down],
+            Monotonicity::None => &[Direction::Any],
+        }
+    }
+
+    /// Whether the step from `a` to `b` is allowed going in `direction`
+    fn is_step_safe(&self, a: i32, b: i32, direction: Direction) -> bool {
+        let step = step(a, b);
+        if step == 0 {
+            return self.allow_equal;
+        }
+
+        let right_way = match direction {
+            Direction::Up => step >= 0,
+            Direction::Down => step <= 0,
+            Direction::Any => true,
+        };
+
+        right_way && (i64::from(self.min_step)..=i64::from(self.max_step)).contains(&step.abs())
+    }
+}
+
+/// Index of the first level whose step to the next one is unsafe
+fn first_unsafe_step(report: &[i32], direction: Direction, policy: &SafetyPolicy) -> Option<usize> {
+    report
         .windows(2)
-        .map(|list| (list[0] - list[1]) - 3)
-        .collect::<Vec<_>>();
+        .position(|pair| !policy.is_step_safe(pair[0], pair[1], direction))
+}
 
-    diff
+// Part one: all steps must be safe
+pub fn is_report_safe(report: &[i32], policy: &SafetyPolicy) -> bool {
+    policy
+        .directions()
         .iter()
-        .all(|a| (-2..=0).contains(a))
-    || diff
+        .any(|&direction| first_unsafe_step(report, direction, policy).is_none())
+}
+
+/// Whether `report` is safe in the given direction once the
+/// level at `skip` is left out
+fn is_safe_without(report: &[i32], skip: usize, direction: Direction, policy: &SafetyPolicy) -> bool {
+    let mut levels = report
         .iter()
-        .all(|a| (-6..=-4).contains(a))
+        .enumerate()
+        .filter(|&(i, _)| i != skip)
+        .map(|(_, &level)| level);
+
+    let Some(mut previous) = levels.next() else {
+        return true;
+    };
+
+    for level in levels {
+        if !policy.is_step_safe(previous, level, direction) {
+            return false;
+        }
+        previous = level;
+    }
+
+    true
+}
+
+/// Why a step between two neighbouring levels is unsafe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepFailure {
+    /// The step goes the opposite way to the rest of the report
+    DirectionChange,
+    /// The two levels are equal
+    ZeroStep,
+    /// The levels change by less than `min_step`
+    TooSmall,
+    /// The levels change by more than `max_step`
+    TooLarge,
 }
 
-// Part two: tolerance of one unsafe step
-pub fn is_report_mostly_safe(report: &[i32]) -> bool {
-    let mut sublists = Vec::<Vec<i32>>::new();
-    for i in 0..report.len() {
-        let mut sublist = report.to_vec();
-        sublist.remove(i);
-        sublists.push(sublist);
+impl std::fmt::Display for StepFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepFailure::DirectionChange => write!(f, "direction change"),
+            StepFailure::ZeroStep => write!(f, "zero step"),
+            StepFailure::TooSmall => write!(f, "step too small"),
+            StepFailure::TooLarge => write!(f, "step too large"),
+        }
     }
+}
 
-    sublists.iter().any(|r| is_report_safe(r))
+/// The first unsafe pair of levels in a report, at `index` and `index + 1`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsafeStep {
+    pub index: usize,
+    pub failure: StepFailure,
 }
 
-fn main() {
+/// How a report fares with the Problem Dampener
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Safe,
+    /// Safe once the level at this index is removed
+    SafeWithout(usize),
+    Unsafe,
+}
+
+/// Why a report is or isn't safe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub verdict: Verdict,
+    /// `None` for safe reports
+    pub first_unsafe: Option<UnsafeStep>,
+}
+
+impl SafetyPolicy {
+    /// Why the step from `a` to `b` isn't allowed going in
+    /// `direction`, if it isn't
+    fn step_failure(&self, a: i32, b: i32, direction: Direction) -> Option<StepFailure> {
+        if self.is_step_safe(a, b, direction) {
+            return None;
+        }
+
+        let step = step(a, b);
+        let wrong_way = match direction {
+            Direction::Up => step < 0,
+            Direction::Down => step > 0,
+            Direction::Any => false,
+        };
+
+        Some(if step == 0 {
+            StepFailure::ZeroStep
+        } else if wrong_way {
+            StepFailure::DirectionChange
+        } else if step.abs() < i64::from(self.min_step) {
+            StepFailure::TooSmall
+        } else {
+            StepFailure::TooLarge
+        })
+    }
+}
+
+/// Explains whether `report` is safe, and if not, where it first
+/// goes wrong and which level (if any) the dampener can remove.
+///
+/// When the report could go either way, the first unsafe step is
+/// taken from the direction the report keeps to for longest.
+pub fn diagnose_report(report: &[i32], policy: &SafetyPolicy) -> Diagnosis {
+    // Called for each direction as needed rather than collected, so
+    // diagnosing a report doesn't allocate
+    let first_unsafe = |direction| first_unsafe_step(report, direction, policy);
+
+    if policy.directions().iter().any(|&direction| first_unsafe(direction).is_none()) {
+        return Diagnosis {
+            verdict: Verdict::Safe,
+            first_unsafe: None,
+        };
+    }
+
+    // Once the first unsafe step is found, removing any level other than
+    // the two either side of it leaves that step in place, so those are
+    // the only two removals worth trying for each direction.
+    let verdict = policy
+        .directions()
+        .iter()
+        .filter_map(|&direction| {
+            let i = first_unsafe(direction).unwrap();
+            [i, i + 1]
+                .into_iter()
+                .find(|&skip| is_safe_without(report, skip, direction, policy))
+        })
+        .map(Verdict::SafeWithout)
+        .next()
+        .unwrap_or(Verdict::Unsafe);
+
+    let (direction, index) = policy
+        .directions()
+        .iter()
+        .map(|&direction| (direction, first_unsafe(direction).unwrap()))
+        .rev()
+        .max_by_key(|&(_, index)| index)
+        .unwrap();
+    let failure = policy
+        .step_failure(report[index], report[index + 1], direction)
+        .unwrap();
+
+    Diagnosis {
+        verdict,
+        first_unsafe: Some(UnsafeStep { index, failure }),
+    }
+}
+
+// Part two: tolerance of one unsafe step. Only the two levels
+// either side of the first unsafe step are worth removing (see
+// `diagnose_report`)
+pub fn is_report_mostly_safe(report: &[i32], policy: &SafetyPolicy) -> bool {
+    policy.directions().iter().any(|&direction| {
+        match first_unsafe_step(report, direction, policy) {
+            None => true,
+            Some(i) => {
+                is_safe_without(report, i, direction, policy)
+                    || is_safe_without(report, i + 1, direction, policy)
+            }
+        }
+    })
+}
+
+/// Minimum number of levels to remove from `report` to make it
+/// safe. A report is safe with a tolerance of `k` if this is at
+/// most `k`, so part one is `k = 0` and part two is `k = 1`.
+///
+/// For each direction, `longest[i]` is the longest safe run of
+/// kept levels ending with level `i`.
+pub fn min_removals(report: &[i32], policy: &SafetyPolicy) -> usize {
+    let mut longest_kept = 0;
+    for &direction in policy.directions() {
+        let mut longest = vec![1; report.len()];
+        for i in 0..report.len() {
+            for j in 0..i {
+                if policy.is_step_safe(report[j], report[i], direction) {
+                    longest[i] = longest[i].max(longest[j] + 1);
+                }
+            }
+        }
+
+        longest_kept = longest.into_iter().max().unwrap_or(0).max(longest_kept);
+    }
+
+    report.len() - longest_kept
+}
+
+/// Whether `report` can be made safe by removing at most `k` levels
+pub fn is_report_safe_within(report: &[i32], k: usize, policy: &SafetyPolicy) -> bool {
+    min_removals(report, policy) <= k
+}
+
+/// Value given after `flag`, if the flag is there
+fn flag_value<'a>(args: &'a [String], flag: &str) -> anyhow::Result<Option<&'a String>> {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => Ok(Some(args.get(i + 1).with_context(|| format!("{} needs a value", flag))?)),
+        None => Ok(None),
+    }
+}
+
+/// Builds the policy from `--min-step <n>`, `--max-step <n>`,
+/// `--allow-equal` and `--monotonicity <increasing|decreasing|either|none>`,
+/// falling back to the puzzle's rules for anything not given.
+fn policy_from_args(args: &[String]) -> anyhow::Result<SafetyPolicy> {
+    let mut policy = SafetyPolicy::default();
+
+    if let Some(min_step) = flag_value(args, "--min-step")? {
+        policy.min_step = min_step.parse()?;
+    }
+    if let Some(max_step) = flag_value(args, "--max-step")? {
+        policy.max_step = max_step.parse()?;
+    }
+    if args.iter().any(|a| a == "--allow-equal") {
+        policy.allow_equal = true;
+    }
+    if let Some(monotonicity) = flag_value(args, "--monotonicity")? {
+        policy.monotonicity = match monotonicity.as_str() {
+            "increasing" => Monotonicity::Increasing,
+            "decreasing" => Monotonicity::Decreasing,
+            "either" => Monotonicity::Either,
+            "none" => Monotonicity::None,
+            _ => bail!("unknown monotonicity: {}", monotonicity),
+        };
+    }
+
+    Ok(policy)
+}
+
+/// Builds the parse options from `--empty <reject|skip|keep>` and
+/// `--single <reject|skip|keep>`
+fn parse_options_from_args(args: &[String]) -> anyhow::Result<ParseOptions> {
+    let handling = |flag: &str, default: ShortReports| -> anyhow::Result<ShortReports> {
+        Ok(match flag_value(args, flag)?.map(String::as_str) {
+            None => default,
+            Some("reject") => ShortReports::Reject,
+            Some("skip") => ShortReports::Skip,
+            Some("keep") => ShortReports::Keep,
+            Some(other) => bail!("{} must be reject, skip or keep, not {}", flag, other),
+        })
+    };
+
+    let defaults = ParseOptions::default();
+    Ok(ParseOptions {
+        empty: handling("--empty", defaults.empty)?,
+        single: handling("--single", defaults.single)?,
+    })
+}
+
+fn try_main() -> anyhow::Result<()> {
     
     // Part one: No dampner applied, all steps must
     // be safe
-    let reports = read_input(include_str!("input.in"));
+    let args = std::env::args().collect::<Vec<_>>();
+    let options = parse_options_from_args(&args)?;
+    let reports = match flag_value(&args, "--input")? {
+        Some(path) => read_input(&std::fs::read_to_string(path)?, &options)?,
+        None => read_input(include_str!("input.in"), &options)?,
+    };
+    let policy = policy_from_args(&args)?;
     let safe_reports = reports
         .iter()
-        .filter(|r| is_report_safe(r))
+        .filter(|r| is_report_safe(&r.levels, &policy))
         .count();
     println!("day two part one: {}", safe_reports);
 
     // Part two: A single unsafe step is allowed
     let mostly_safe_reports = reports
         .iter()
-        .filter(|r| is_report_mostly_safe(r));
+        .filter(|r| is_report_mostly_safe(&r.levels, &policy));
 
     let test = mostly_safe_reports.cloned().collect::<Vec<_>>();
     println!("day two part two: {}", test.len());
+
+    // Pass `--histogram` to see how many levels each report needs
+    // removing to be safe
+    if args.iter().any(|a| a == "--histogram") {
+        let mut histogram = std::collections::BTreeMap::<usize, usize>::new();
+        for report in &reports {
+            *histogram.entry(min_removals(&report.levels, &policy)).or_default() += 1;
+        }
+
+        for (removals, count) in histogram {
+            println!("{} removals: {}", removals, count);
+        }
+    }
+
+    // Pass `--explain` to see why each report is or isn't safe
+    if args.iter().any(|a| a == "--explain") {
+        for Report { line, levels } in &reports {
+            let diagnosis = diagnose_report(levels, &policy);
+            let verdict = match diagnosis.verdict {
+                Verdict::Safe => "safe".to_string(),
+                Verdict::SafeWithout(i) => format!("safe without level {} ({})", i, levels[i]),
+                Verdict::Unsafe => "unsafe".to_string(),
+            };
+
+            match diagnosis.first_unsafe {
+                Some(step) => println!(
+                    "{}: {:?}: {} at {} ({} -> {}), {}",
+                    line,
+                    levels,
+                    step.failure,
+                    step.index,
+                    levels[step.index],
+                    levels[step.index + 1],
+                    verdict
+                ),
+                None => println!("{}: {:?}: {}", line, levels, verdict),
+            }
+        }
+    }
+
+    // Pass `--tolerance <k>` to count the reports that are safe
+    // after removing up to `k` levels
+    if let Some(k) = flag_value(&args, "--tolerance")? {
+        let k = k.parse::<usize>()?;
+        let count = reports
+            .iter()
+            .filter(|r| is_report_safe_within(&r.levels, k, &policy))
+            .count();
+        println!("safe with {} removals: {}", k, count);
+    }
+
+    Ok(())
+}
+
+fn main() {
+    match try_main() {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("finished with error: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    /// The original part one, before `SafetyPolicy`
+    fn is_report_safe_original(report: &[i32]) -> bool {
+        let diff = report
+            .windows(2)
+            .map(|list| (list[0] - list[1]) - 3)
+            .collect::<Vec<_>>();
+
+        diff.iter().all(|a| (-2..=0).contains(a)) || diff.iter().all(|a| (-6..=-4).contains(a))
+    }
+
+    /// The original part two: try removing every level in turn
+    fn is_report_mostly_safe_brute_force(report: &[i32], policy: &SafetyPolicy) -> bool {
+        let mut sublists = Vec::<Vec<i32>>::new();
+        for i in 0..report.len() {
+            let mut sublist = report.to_vec();
+            sublist.remove(i);
+            sublists.push(sublist);
+        }
+
+        sublists.iter().any(|r| is_report_safe(r, policy))
+    }
+
+    /// Picks one of a spread of policies, including the default
+    fn policy_from_seed(seed: u8) -> SafetyPolicy {
+        let monotonicity = [
+            Monotonicity::Either,
+            Monotonicity::Increasing,
+            Monotonicity::Decreasing,
+            Monotonicity::None,
+        ][seed as usize % 4];
+
+        SafetyPolicy {
+            min_step: (seed as i32 / 4) % 2,
+            max_step: 2 + (seed as i32 / 8) % 3,
+            allow_equal: (seed / 24) % 2 == 1,
+            monotonicity,
+        }
+    }
+
+    /// Short reports with levels close together, so that safe
+    /// reports and near misses actually come up
+    fn small_report(levels: &[u8]) -> Vec<i32> {
+        levels
+            .iter()
+            .take(8)
+            .map(|&l| (l % 12) as i32)
+            .collect()
+    }
+
+    #[quickcheck]
+    fn default_policy_matches_original(levels: Vec<u8>) -> bool {
+        let report = small_report(&levels);
+        is_report_safe(&report, &SafetyPolicy::default()) == is_report_safe_original(&report)
+    }
+
+    #[quickcheck]
+    fn mostly_safe_matches_brute_force(levels: Vec<u8>, seed: u8) -> bool {
+        let report = small_report(&levels);
+        if report.is_empty() {
+            return true;
+        }
+
+        let policy = policy_from_seed(seed);
+        is_report_mostly_safe(&report, &policy) == is_report_mostly_safe_brute_force(&report, &policy)
+    }
+
+    #[quickcheck]
+    fn diagnosis_matches_mostly_safe(levels: Vec<u8>, seed: u8) -> bool {
+        let report = small_report(&levels);
+        let policy = policy_from_seed(seed);
+        let verdict = diagnose_report(&report, &policy).verdict;
+
+        (verdict == Verdict::Safe) == is_report_safe(&report, &policy)
+            && (verdict != Verdict::Unsafe) == is_report_mostly_safe(&report, &policy)
+    }
+
+    #[quickcheck]
+    fn min_removals_matches_parts(levels: Vec<u8>, seed: u8) -> bool {
+        let report = small_report(&levels);
+        let policy = policy_from_seed(seed);
+        if report.is_empty() {
+            return min_removals(&report, &policy) == 0;
+        }
+
+        is_report_safe_within(&report, 0, &policy) == is_report_safe(&report, &policy)
+            && is_report_safe_within(&report, 1, &policy) == is_report_mostly_safe(&report, &policy)
+    }
+
+    #[quickcheck]
+    fn equal_neighbours_need_allow_equal(start: i8, decreasing: bool, steps: Vec<u8>, seed: u8) -> bool {
+        // Small steps one way, with plenty of equal neighbours
+        let report = steps
+            .iter()
+            .take(6)
+            .scan(start as i32, |level, &step| {
+                let step = [0, 1, 2, 0, 1][step as usize % 5];
+                *level += if decreasing { -step } else { step };
+                Some(*level)
+            })
+            .collect::<Vec<_>>();
+        let has_equal = report.windows(2).any(|pair| pair[0] == pair[1]);
+
+        // Half the seeds have a `min_step` of 0, which must not let
+        // equal levels through on its own
+        let policy = SafetyPolicy {
+            allow_equal: false,
+            ..policy_from_seed(seed)
+        };
+        let allowing = SafetyPolicy {
+            allow_equal: true,
+            ..policy.clone()
+        };
+
+        !(has_equal && is_report_safe(&report, &policy))
+            && (is_report_safe(&report, &allowing) || !is_report_safe(&report, &policy))
+    }
+
+    fn parse_error(input: &str, options: &ParseOptions) -> String {
+        format!("{:#}", read_input(input, options).unwrap_err())
+    }
+
+    #[test]
+    fn bad_levels_are_located() {
+        let error = parse_error("1 2 3\n4  x 6\n", &ParseOptions::default());
+        assert_eq!(error, "line 2, token 2 (column 4): invalid level `x`: invalid digit found in string");
+
+        // Columns count bytes, so this is after the 2 byte `é`
+        let error = parse_error("\t7 é", &ParseOptions::default());
+        assert!(error.starts_with("line 1, token 2 (column 4): invalid level `é`"), "{}", error);
+    }
+
+    #[test]
+    fn comments_are_skipped_and_lines_kept() {
+        let input = "# levels\n1 2 3\n  # indented\n\n4 5 6\n";
+        let options = ParseOptions {
+            empty: ShortReports::Skip,
+            ..ParseOptions::default()
+        };
+
+        assert_eq!(
+            read_input(input, &options).unwrap(),
+            [
+                Report { line: 2, levels: vec![1, 2, 3] },
+                Report { line: 5, levels: vec![4, 5, 6] },
+            ]
+        );
+    }
+
+    #[test]
+    fn short_reports() {
+        let input = "1 2\n\n3\n";
+        let options = |empty, single| ParseOptions { empty, single };
+        let lines = |options| {
+            read_input(input, &options)
+                .unwrap()
+                .iter()
+                .map(|report| report.line)
+                .collect::<Vec<_>>()
+        };
+
+        // By default a blank line is an error, but a single level is kept
+        assert_eq!(parse_error(input, &ParseOptions::default()), "line 2: empty report");
+        assert_eq!(lines(options(ShortReports::Skip, ShortReports::Keep)), [1, 3]);
+
+        assert_eq!(lines(options(ShortReports::Keep, ShortReports::Keep)), [1, 2, 3]);
+        assert_eq!(lines(options(ShortReports::Skip, ShortReports::Skip)), [1]);
+        assert_eq!(lines(options(ShortReports::Keep, ShortReports::Skip)), [1, 2]);
+        assert_eq!(
+            parse_error(input, &options(ShortReports::Skip, ShortReports::Reject)),
+            "line 3: report with a single level"
+        );
+        assert_eq!(
+            read_input(input, &options(ShortReports::Keep, ShortReports::Keep)).unwrap()[1],
+            Report { line: 2, levels: vec![] }
+        );
+    }
+
+    #[test]
+    fn extreme_levels_dont_overflow() {
+        let report = [i32::MAX, i32::MIN];
+        let policy = SafetyPolicy::default();
+        assert!(!is_report_safe(&report, &policy));
+        assert!(is_report_mostly_safe(&report, &policy));
+        assert_eq!(policy.step_failure(i32::MAX, i32::MIN, Direction::Up), Some(StepFailure::DirectionChange));
+        assert_eq!(policy.step_failure(i32::MAX, i32::MIN, Direction::Down), Some(StepFailure::TooLarge));
+
+        let anything = SafetyPolicy {
+            min_step: 0,
+            max_step: i32::MAX,
+            allow_equal: true,
+            monotonicity: Monotonicity::None,
+        };
+        assert!(is_report_safe(&[i32::MIN, -1, i32::MAX - 1], &anything));
+        assert!(!is_report_safe(&[i32::MIN, i32::MAX], &anything));
+    }
+
+    #[quickcheck]
+    fn mostly_safe_matches_brute_force_on_monotonic(start: i8, decreasing: bool, steps: Vec<u8>) -> bool {
+        // Mostly safe reports with the odd bad step thrown in
+        let report = steps
+            .iter()
+            .take(8)
+            .scan(start as i32, |level, &step| {
+                let step = [1, 2, 3, 1, 2, 3, 0, 4, -1, -3][step as usize % 10];
+                *level += if decreasing { -step } else { step };
+                Some(*level)
+            })
+            .collect::<Vec<_>>();
+        if report.is_empty() {
+            return true;
+        }
+
+        let policy = SafetyPolicy::default();
+        is_report_mostly_safe(&report, &policy) == is_report_mostly_safe_brute_force(&report, &policy)
+    }
 }
@@ -0,0 +1,104 @@
+use std::ops::RangeInclusive;
+
+/// How many arguments an instruction takes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+/// What running an instruction does
+#[derive(Clone, Copy, Debug)]
+pub enum Effect {
+    /// Adds the result to the running totals. `None` if the result
+    /// doesn't fit in an `i64`
+    Value(fn(&[i64]) -> Option<i64>),
+    /// Lets the following instructions count towards the enabled total
+    Enable,
+    /// Stops the following instructions counting towards the enabled total
+    Disable,
+}
+
+/// An instruction the scanner should pick up, written in the
+/// memory as `name(a,b,...)`
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub name: &'static str,
+    pub arity: Arity,
+    /// How many digits each argument may have
+    pub digits: RangeInclusive<usize>,
+    pub effect: Effect,
+}
+
+impl Instruction {
+    pub fn new(name: &'static str, arity: Arity, effect: Effect) -> Self {
+        Instruction {
+            name,
+            arity,
+            digits: 1..=3,
+            effect,
+        }
+    }
+
+    pub fn with_digits(mut self, digits: RangeInclusive<usize>) -> Self {
+        self.digits = digits;
+        self
+    }
+}
+
+/// The instructions known to the scanner and interpreter. Tokens
+/// refer to instructions by their index in the set.
+#[derive(Clone, Debug, Default)]
+pub struct InstructionSet {
+    instructions: Vec<Instruction>,
+}
+
+impl InstructionSet {
+    pub fn with(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    pub fn get(&self, index: usize) -> &Instruction {
+        &self.instructions[index]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Instruction)> {
+        self.instructions.iter().enumerate()
+    }
+
+    /// The puzzle's instructions: `mul(X,Y)`, `do()` and `don't()`
+    pub fn puzzle() -> Self {
+        InstructionSet::default()
+            .with(Instruction::new("mul", Arity::Exactly(2), Effect::Value(|args| args[0].checked_mul(args[1]))))
+            .with(Instruction::new("do", Arity::Exactly(0), Effect::Enable))
+            .with(Instruction::new("don't", Arity::Exactly(0), Effect::Disable))
+    }
+
+    /// The instructions of our variant inputs: `add` and `sub` (with
+    /// up to 5 digit operands) on top of the puzzle's, with `mul`
+    /// taking any number of arguments
+    pub fn extended() -> Self {
+        InstructionSet::default()
+            .with(Instruction::new("mul", Arity::AtLeast(1), Effect::Value(|args| args.iter().try_fold(1i64, |p, &a| p.checked_mul(a)))))
+            .with(
+                Instruction::new("add", Arity::AtLeast(1), Effect::Value(|args| args.iter().try_fold(0i64, |s, &a| s.checked_add(a))))
+                    .with_digits(1..=5),
+            )
+            .with(
+                Instruction::new("sub", Arity::Exactly(2), Effect::Value(|args| args[0].checked_sub(args[1])))
+                    .with_digits(1..=5),
+            )
+            .with(Instruction::new("do", Arity::Exactly(0), Effect::Enable))
+            .with(Instruction::new("don't", Arity::Exactly(0), Effect::Disable))
+    }
+}
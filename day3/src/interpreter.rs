@@ -0,0 +1,107 @@
+use anyhow::Context;
+
+use crate::instructions::{Effect, InstructionSet};
+use crate::lexer::Token;
+
+/// Sums of the instructions run so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// Every instruction, ignoring `do()` and `don't()` (part one)
+    pub all: i64,
+    /// Only the instructions that were enabled at the time (part two)
+    pub enabled: i64,
+}
+
+/// Runs instructions in order. Instructions start enabled, and
+/// toggles like `don't()` and `do()` switch them off and on again.
+#[derive(Clone, Debug)]
+pub struct Interpreter<'a> {
+    set: &'a InstructionSet,
+    enabled: bool,
+    totals: Totals,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(set: &'a InstructionSet) -> Self {
+        Interpreter {
+            set,
+            enabled: true,
+            totals: Totals::default(),
+        }
+    }
+
+    /// Whether an instruction run now would count towards the
+    /// enabled total
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Runs one instruction, returning its result if it has one.
+    /// Fails if the result or either total overflows.
+    pub fn step(&mut self, token: &Token) -> anyhow::Result<Option<i64>> {
+        let instruction = self.set.get(token.instruction);
+        match instruction.effect {
+            Effect::Value(evaluate) => {
+                let overflow = || format!("overflow running `{}` at offset {}", instruction.name, token.offset);
+                let value = evaluate(&token.args).with_context(overflow)?;
+                self.totals.all = self.totals.all.checked_add(value).with_context(overflow)?;
+                if self.enabled {
+                    self.totals.enabled = self.totals.enabled.checked_add(value).with_context(overflow)?;
+                }
+                Ok(Some(value))
+            },
+            Effect::Enable => {
+                self.enabled = true;
+                Ok(None)
+            },
+            Effect::Disable => {
+                self.enabled = false;
+                Ok(None)
+            },
+        }
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+}
+
+/// Runs all the `tokens` from a fresh state, giving both parts' sums
+pub fn run(set: &InstructionSet, tokens: &[Token]) -> anyhow::Result<Totals> {
+    let mut interpreter = Interpreter::new(set);
+    for token in tokens {
+        interpreter.step(token)?;
+    }
+
+    Ok(interpreter.totals())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::{Arity, Instruction};
+    use crate::lexer;
+
+    #[test]
+    fn overflow_is_an_error() {
+        let set = InstructionSet::extended();
+        let tokens = lexer::lex("mul(999,999,999,999,999,999,999)", &set);
+        assert_eq!(tokens.len(), 1);
+        assert!(run(&set, &tokens).is_err());
+
+        // Each fits, but not the total
+        let input = "mul(999,999,999,999,999,999)".repeat(10);
+        assert!(run(&set, &lexer::lex(&input, &set)).is_err());
+    }
+
+    #[test]
+    fn numbers_too_big_for_i64_are_skipped() {
+        let add = Instruction::new("add", Arity::AtLeast(1), Effect::Value(|args| args.iter().try_fold(0i64, |s, &a| s.checked_add(a))))
+            .with_digits(1..=25);
+        let set = InstructionSet::default().with(add);
+
+        let tokens = lexer::lex("add(99999999999999999999,1)add(9223372036854775807,0)", &set);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(run(&set, &tokens).unwrap().all, i64::MAX);
+    }
+}
@@ -0,0 +1,144 @@
+use std::ops::RangeInclusive;
+
+use crate::instructions::InstructionSet;
+
+/// An instruction found in the memory, starting `offset` bytes in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// Index of the instruction in its `InstructionSet`
+    pub instruction: usize,
+    pub args: Vec<i64>,
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// Outcome of trying to read something at the start of the input
+enum Scan<T> {
+    Found(T),
+    /// Definitely not there
+    Missing,
+    /// The input ran out part way through, so more input might
+    /// still make it match
+    Truncated,
+}
+
+/// Reads a number with an allowed number of `digits` at the start
+/// of `input`, returning it along with how many bytes it took up.
+/// Numbers too big for an `i64` don't count.
+fn number(input: &[u8], digits: &RangeInclusive<usize>) -> Scan<(i64, usize)> {
+    let len = input
+        .iter()
+        .take(digits.end() + 1)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+
+    if len == input.len() && len <= *digits.end() {
+        return Scan::Truncated;
+    }
+
+    if !digits.contains(&len) {
+        return Scan::Missing;
+    }
+
+    let value = input[..len].iter().try_fold(0i64, |value, digit| {
+        value.checked_mul(10)?.checked_add((digit - b'0') as i64)
+    });
+    match value {
+        Some(value) => Scan::Found((value, len)),
+        None => Scan::Missing,
+    }
+}
+
+/// Reads the `(a,b,...)` argument list at the start of `input`,
+/// returning the arguments and how many bytes it took up.
+fn arguments(input: &[u8], digits: &RangeInclusive<usize>) -> Scan<(Vec<i64>, usize)> {
+    match input.first() {
+        None => return Scan::Truncated,
+        Some(b'(') => {},
+        Some(_) => return Scan::Missing,
+    }
+
+    let mut args = vec![];
+    let mut pos = 1;
+    match input.get(pos) {
+        None => return Scan::Truncated,
+        Some(b')') => return Scan::Found((args, pos + 1)),
+        Some(_) => {},
+    }
+
+    loop {
+        match number(&input[pos..], digits) {
+            Scan::Found((arg, len)) => {
+                args.push(arg);
+                pos += len;
+            },
+            Scan::Missing => return Scan::Missing,
+            Scan::Truncated => return Scan::Truncated,
+        }
+
+        match input.get(pos) {
+            None => return Scan::Truncated,
+            Some(b',') => pos += 1,
+            Some(b')') => return Scan::Found((args, pos + 1)),
+            Some(_) => return Scan::Missing,
+        }
+    }
+}
+
+/// Tries to read any instruction of `set` at the start of `input`
+fn token(input: &[u8], set: &InstructionSet) -> Scan<(usize, Vec<i64>, usize)> {
+    let mut truncated = false;
+    for (index, instruction) in set.iter() {
+        let name = instruction.name.as_bytes();
+        if !input.starts_with(name) {
+            truncated |= name.starts_with(input);
+            continue;
+        }
+
+        match arguments(&input[name.len()..], &instruction.digits) {
+            Scan::Found((args, len)) if instruction.arity.accepts(args.len()) => {
+                return Scan::Found((index, args, name.len() + len));
+            },
+            Scan::Found(_) | Scan::Missing => {},
+            Scan::Truncated => truncated = true,
+        }
+    }
+
+    if truncated {
+        Scan::Truncated
+    } else {
+        Scan::Missing
+    }
+}
+
+/// Scans `input` left to right, picking up every instruction of
+/// `set` and skipping over everything else. Token offsets are
+/// counted from `base`.
+///
+/// Unless `at_end` is set, scanning stops at the first instruction
+/// that might carry on past the end of `input`. Returns the tokens
+/// along with how many bytes were dealt with, so the caller can
+/// keep the rest for when more input arrives.
+pub fn lex_bytes(input: &[u8], set: &InstructionSet, base: usize, at_end: bool) -> (Vec<Token>, usize) {
+    let mut tokens = vec![];
+    let mut offset = 0;
+    while offset < input.len() {
+        match token(&input[offset..], set) {
+            Scan::Found((instruction, args, len)) => {
+                tokens.push(Token { instruction, args, offset: base + offset, len });
+                offset += len;
+            },
+            Scan::Truncated if !at_end => break,
+            Scan::Truncated | Scan::Missing => {
+                offset += 1;
+            }
+        }
+    }
+
+    (tokens, offset)
+}
+
+/// Scans the whole corrupted memory at once
+pub fn lex(input: &str, set: &InstructionSet) -> Vec<Token> {
+    lex_bytes(input.as_bytes(), set, 0, true).0
+}
@@ -0,0 +1,146 @@
+use crate::instructions::InstructionSet;
+use crate::interpreter::Interpreter;
+use crate::lexer::Token;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// A recognised instruction, where it is, and what it did
+#[derive(Clone, Debug)]
+pub struct Entry<'a> {
+    pub token: &'a Token,
+    /// 1-based line and column (in characters) of the instruction
+    pub line: usize,
+    pub column: usize,
+    /// Whether the interpreter was enabled when it got here
+    pub enabled: bool,
+    /// The result, for instructions that produce one
+    pub value: Option<i64>,
+}
+
+/// Byte offsets where each line of `input` starts
+pub fn line_starts(input: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+/// 1-based line and column (in characters) of byte `offset` in
+/// `input`, given the byte offsets where each line starts
+pub fn line_column(input: &str, line_starts: &[usize], offset: usize) -> (usize, usize) {
+    let line = line_starts.partition_point(|&start| start <= offset);
+    let column = input[line_starts[line - 1]..offset].chars().count() + 1;
+    (line, column)
+}
+
+/// Runs the `tokens` through an interpreter, recording the state
+/// and result of each one.
+pub fn listing<'a>(input: &str, set: &InstructionSet, tokens: &'a [Token]) -> anyhow::Result<Vec<Entry<'a>>> {
+    let line_starts = line_starts(input);
+
+    let mut interpreter = Interpreter::new(set);
+    tokens
+        .iter()
+        .map(|token| {
+            let (line, column) = line_column(input, &line_starts, token.offset);
+            let enabled = interpreter.is_enabled();
+            let value = interpreter.step(token)?;
+            Ok(Entry { token, line, column, enabled, value })
+        })
+        .collect()
+}
+
+/// Prints one line per entry in the listing
+pub fn print_listing(input: &str, entries: &[Entry]) {
+    for entry in entries {
+        let token = entry.token;
+        let text = &input[token.offset..token.offset + token.len];
+        let state = if entry.enabled { "enabled" } else { "disabled" };
+        let value = entry.value.map(|v| v.to_string()).unwrap_or_default();
+
+        println!(
+            "{:>6} {:>4}:{:<5} {:<9} {:<20} {}",
+            token.offset, entry.line, entry.column, state, text, value
+        );
+    }
+}
+
+/// The input with the recognised instructions coloured in: green
+/// for enabled ones, red for disabled ones and yellow for toggles.
+/// Everything left uncoloured was skipped as corrupted.
+pub fn highlight(input: &str, entries: &[Entry]) -> String {
+    let mut highlighted = String::new();
+    let mut last = 0;
+    for entry in entries {
+        let token = entry.token;
+        let colour = match (entry.value, entry.enabled) {
+            (None, _) => YELLOW,
+            (Some(_), true) => GREEN,
+            (Some(_), false) => RED,
+        };
+
+        highlighted.push_str(&input[last..token.offset]);
+        highlighted.push_str(colour);
+        highlighted.push_str(&input[token.offset..token.offset + token.len]);
+        highlighted.push_str(RESET);
+        last = token.offset + token.len;
+    }
+
+    highlighted.push_str(&input[last..]);
+    highlighted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+
+    const INPUT: &str = "é mul(2,3)\nxx☃do()don't()mul(4,5)\n";
+
+    #[test]
+    fn lines_and_columns() {
+        let starts = line_starts(INPUT);
+        assert_eq!(starts, [0, 12, 37]);
+
+        // `é` and `☃` are one column each, despite their extra bytes
+        assert_eq!(line_column(INPUT, &starts, 0), (1, 1));
+        assert_eq!(line_column(INPUT, &starts, 3), (1, 3));
+        assert_eq!(line_column(INPUT, &starts, 12), (2, 1));
+        assert_eq!(line_column(INPUT, &starts, 17), (2, 4));
+    }
+
+    #[test]
+    fn entries() {
+        let set = InstructionSet::puzzle();
+        let tokens = lexer::lex(INPUT, &set);
+        let entries = listing(INPUT, &set, &tokens).unwrap();
+
+        let summary = entries
+            .iter()
+            .map(|e| (e.line, e.column, e.enabled, e.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (1, 3, true, Some(6)),
+                (2, 4, true, None),
+                (2, 8, true, None),
+                (2, 15, false, Some(20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlighting() {
+        let set = InstructionSet::puzzle();
+        let tokens = lexer::lex(INPUT, &set);
+        let highlighted = highlight(INPUT, &listing(INPUT, &set, &tokens).unwrap());
+
+        let expected = format!(
+            "é {GREEN}mul(2,3){RESET}\nxx☃{YELLOW}do(){RESET}{YELLOW}don't(){RESET}{RED}mul(4,5){RESET}\n"
+        );
+        assert_eq!(highlighted, expected);
+    }
+}
@@ -1,55 +1,78 @@
-use regex::Regex;
+use anyhow::Context;
 
-fn get_instruction_str(input: &str) -> anyhow::Result<String> {
+mod instructions;
+mod interpreter;
+mod lexer;
+mod listing;
+mod near_miss;
+mod stream;
 
-    let mut new_input = input.to_string();
-    new_input.insert_str(0, "do()");
-    new_input.push_str("don't()");
+use instructions::InstructionSet;
 
-    // This will give me strings with don't() in it
-    // we need to ignore everything after that
-    Ok(new_input
-        .split("do()")
-        .map(|substr| substr[..substr.find("don't").unwrap_or(substr.len())].to_string())
-        .fold(String::new(), |a, b| format!("{}{}", a, b)))
-}
+fn try_main() -> anyhow::Result<()> {
 
-fn get_multiply_pairs(input: &str) -> anyhow::Result<Vec<(i32, i32)>> {
-    let pattern = r"mul\((?<a>[0-9]{1,3}),(?<b>[0-9]{1,3})\)";
-    let re = Regex::new(pattern)?;
+    let args = std::env::args().collect::<Vec<_>>();
 
-    let captures = re.captures_iter(input);
+    // Pass `--extended` to also pick up `add`, `sub` and `mul`s
+    // with any number of arguments
+    let extended = args.iter().any(|a| a == "--extended");
+    let set = if extended {
+        InstructionSet::extended()
+    } else {
+        InstructionSet::puzzle()
+    };
 
-    // Vec<(str, str)>
-    Ok(captures
-        .map(|c| (c["a"].parse::<i32>().unwrap(), c["b"].parse::<i32>().unwrap()))
-        .collect::<Vec<_>>())
-}
+    // Pass `--input <file>` to read another memory dump
+    let path = match args.iter().position(|a| a == "--input") {
+        Some(i) => Some(args.get(i + 1).context("--input needs a file")?),
+        None => None,
+    };
 
-fn try_main() -> anyhow::Result<()> {
+    // Run the memory through the lexer and the interpreter to get
+    // both parts in one pass, streaming files rather than loading them
+    let totals = match path {
+        Some(path) => stream::evaluate(std::fs::File::open(path)?, &set)?,
+        None => {
+            let input = include_str!("day3.in");
+            interpreter::run(&set, &lexer::lex(input, &set))?
+        },
+    };
 
     // Part one: find all valid mul(X,Y) instructions,
     // and operate and sum them up
-    // Read the input
-    // let input = include_str!("day3.in");
-    let input = include_str!("day3.in");
-    let valid_instructions = get_multiply_pairs(input)?;
-    let sum_of_mul: i32 = valid_instructions
-        .iter()
-        .map(|(a, b)| a * b)
-        .sum();
-    println!("day3 part one: {}", sum_of_mul);
-
+    println!("day3 part one: {}", totals.all);
 
     // Part two: We only care for what's inside "do()" and "don't()"
     // strings lazily
-    let do_instructions = get_instruction_str(input)?;
-    let valid_instructions = get_multiply_pairs(&do_instructions)?;
-    let sum_of_mul: i32 = valid_instructions
-        .iter()
-        .map(|(a, b)| a * b)
-        .sum();
-    println!("day3 part two: {}", sum_of_mul);
+    println!("day3 part two: {}", totals.enabled);
+
+    // Pass `--list` to print every instruction found, and
+    // `--highlight` to print the input with them coloured in.
+    // These need the whole input in memory
+    // `--near-misses` prints what almost was an instruction, and
+    // why it wasn't
+    let list = args.iter().any(|a| a == "--list");
+    let highlight = args.iter().any(|a| a == "--highlight");
+    let near_misses = args.iter().any(|a| a == "--near-misses");
+    if list || highlight || near_misses {
+        let input = match path {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => include_str!("day3.in").to_string(),
+        };
+
+        let tokens = lexer::lex(&input, &set);
+        let entries = listing::listing(&input, &set, &tokens)?;
+        if list {
+            listing::print_listing(&input, &entries);
+        }
+        if highlight {
+            println!("{}", listing::highlight(&input, &entries));
+        }
+        if near_misses {
+            let misses = near_miss::find_near_misses(&input, &set)?;
+            near_miss::print_near_misses(&input, &misses);
+        }
+    }
 
     Ok(())
 }
@@ -64,3 +87,57 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// The original part two: keep only what's between a `do()`
+    /// and the next `don't()`
+    fn get_instruction_str(input: &str) -> anyhow::Result<String> {
+
+        let mut new_input = input.to_string();
+        new_input.insert_str(0, "do()");
+        new_input.push_str("don't()");
+
+        // This will give me strings with don't() in it
+        // we need to ignore everything after that
+        Ok(new_input
+            .split("do()")
+            .map(|substr| &substr[..substr.find("don't").unwrap_or(substr.len())])
+            .collect::<String>())
+    }
+
+    /// The original part one
+    fn get_multiply_pairs(input: &str) -> anyhow::Result<Vec<(i32, i32)>> {
+        let pattern = r"mul\((?<a>[0-9]{1,3}),(?<b>[0-9]{1,3})\)";
+        let re = Regex::new(pattern)?;
+
+        let captures = re.captures_iter(input);
+
+        // Vec<(str, str)>
+        Ok(captures
+            .map(|c| (c["a"].parse::<i32>().unwrap(), c["b"].parse::<i32>().unwrap()))
+            .collect::<Vec<_>>())
+    }
+
+    fn sum_of_mul(input: &str) -> i64 {
+        get_multiply_pairs(input)
+            .unwrap()
+            .iter()
+            .map(|&(a, b)| a as i64 * b as i64)
+            .sum()
+    }
+
+    #[test]
+    fn matches_regex_solution() {
+        let input = include_str!("day3.in");
+        let set = InstructionSet::puzzle();
+        let totals = interpreter::run(&set, &lexer::lex(input, &set)).unwrap();
+
+        assert_eq!(totals.all, sum_of_mul(input));
+        assert_eq!(totals.enabled, sum_of_mul(&get_instruction_str(input).unwrap()));
+        assert_eq!(totals, interpreter::Totals { all: 173529487, enabled: 99532691 });
+    }
+}
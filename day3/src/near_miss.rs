@@ -0,0 +1,207 @@
+use regex::Regex;
+
+use crate::instructions::InstructionSet;
+use crate::listing::{line_column, line_starts};
+
+/// Why something that looks like an instruction isn't one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// Space between the name and the `(`, like `do ()`
+    SpaceBeforeParen,
+    /// Arguments, but no `)` after them
+    Unclosed,
+    /// Spaces or tabs between the parentheses
+    Whitespace,
+    /// An argument with a sign, like `mul(-2,4)`
+    Signed(String),
+    /// An argument with too few or too many digits
+    Digits { arg: String, allowed: std::ops::RangeInclusive<usize> },
+    /// Nothing between two commas, or around one
+    EmptyArgument,
+    /// The wrong number of arguments for the instruction
+    Arity(usize),
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::SpaceBeforeParen => write!(f, "space before `(`"),
+            Rule::Unclosed => write!(f, "missing `)`"),
+            Rule::Whitespace => write!(f, "whitespace in the arguments"),
+            Rule::Signed(arg) => write!(f, "signed argument `{}`", arg),
+            Rule::Digits { arg, allowed } => write!(
+                f,
+                "argument `{}` has {} digits, expected {} to {}",
+                arg,
+                arg.len(),
+                allowed.start(),
+                allowed.end()
+            ),
+            Rule::EmptyArgument => write!(f, "empty argument"),
+            Rule::Arity(found) => write!(f, "wrong number of arguments ({})", found),
+        }
+    }
+}
+
+/// Something that almost was an instruction, `len` bytes at
+/// `offset`, and the first rule it broke
+#[derive(Clone, Debug)]
+pub struct NearMiss {
+    pub offset: usize,
+    pub len: usize,
+    pub rule: Rule,
+}
+
+/// Picks up anything shaped like `name(...)` for the names in `set`,
+/// allowing the mistakes in `Rule`. Longer names go first so `don't`
+/// isn't read as `do`.
+fn loose_pattern(set: &InstructionSet) -> anyhow::Result<Regex> {
+    let mut names = set.iter().map(|(_, i)| i.name).collect::<Vec<_>>();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let names = names.into_iter().map(regex::escape).collect::<Vec<_>>().join("|");
+
+    let pattern = format!(r"(?<name>{})(?<space>[ \t]*)\((?<args>[-+0-9, \t]*)(?<close>\))?", names);
+    Ok(Regex::new(&pattern)?)
+}
+
+/// The first rule broken by instruction `index` of `set` written
+/// with `args` between its parentheses, if any
+fn broken_rule(set: &InstructionSet, index: usize, args: &str, closed: bool) -> Option<Rule> {
+    let instruction = set.get(index);
+    if !closed {
+        return Some(Rule::Unclosed);
+    }
+
+    if args.contains([' ', '\t']) {
+        return Some(Rule::Whitespace);
+    }
+
+    let args = if args.is_empty() {
+        vec![]
+    } else {
+        args.split(',').collect::<Vec<_>>()
+    };
+
+    for arg in &args {
+        if arg.is_empty() {
+            return Some(Rule::EmptyArgument);
+        }
+        if arg.contains(['-', '+']) {
+            return Some(Rule::Signed(arg.to_string()));
+        }
+        if !instruction.digits.contains(&arg.len()) {
+            return Some(Rule::Digits {
+                arg: arg.to_string(),
+                allowed: instruction.digits.clone(),
+            });
+        }
+    }
+
+    if !instruction.arity.accepts(args.len()) {
+        return Some(Rule::Arity(args.len()));
+    }
+
+    None
+}
+
+/// Finds the places in `input` that look like instructions of `set`
+/// but that the lexer skips over, like `mul(1234,5)`, `mul( 2,3)`,
+/// `mul(2,3` or `do ()`. A name followed by a `(` and nothing
+/// argument-like is taken as plain corruption, not a near miss.
+pub fn find_near_misses(input: &str, set: &InstructionSet) -> anyhow::Result<Vec<NearMiss>> {
+    let re = loose_pattern(set)?;
+
+    let mut misses = vec![];
+    for captures in re.captures_iter(input) {
+        let whole = captures.get(0).unwrap();
+        let name = &captures["name"];
+        let args = &captures["args"];
+        let closed = captures.name("close").is_some();
+
+        if !closed && args.trim().is_empty() {
+            continue;
+        }
+
+        let (index, _) = set.iter().find(|(_, i)| i.name == name).unwrap();
+        let rule = if captures["space"].is_empty() {
+            broken_rule(set, index, args, closed)
+        } else {
+            Some(Rule::SpaceBeforeParen)
+        };
+
+        if let Some(rule) = rule {
+            misses.push(NearMiss {
+                offset: whole.start(),
+                len: whole.len(),
+                rule,
+            });
+        }
+    }
+
+    Ok(misses)
+}
+
+/// Prints one line per near miss
+pub fn print_near_misses(input: &str, misses: &[NearMiss]) {
+    let line_starts = line_starts(input);
+    for miss in misses {
+        let (line, column) = line_column(input, &line_starts, miss.offset);
+        let text = &input[miss.offset..miss.offset + miss.len];
+        println!(
+            "{:>6} {:>4}:{:<5} {:<20} {}",
+            miss.offset, line, column, text, miss.rule
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text and rule of each near miss in `input`
+    fn near_misses<'a>(input: &'a str, set: &InstructionSet) -> Vec<(&'a str, Rule)> {
+        find_near_misses(input, set)
+            .unwrap()
+            .into_iter()
+            .map(|miss| (&input[miss.offset..miss.offset + miss.len], miss.rule))
+            .collect()
+    }
+
+    #[test]
+    fn one_of_each_rule() {
+        let input = "mul(1234,5)mul( 2,3)mul(2,3 do ()mul(-2,4)mul(2,,3)mul(2)";
+        assert_eq!(
+            near_misses(input, &InstructionSet::puzzle()),
+            [
+                ("mul(1234,5)", Rule::Digits { arg: "1234".to_string(), allowed: 1..=3 }),
+                ("mul( 2,3)", Rule::Whitespace),
+                ("mul(2,3 ", Rule::Unclosed),
+                ("do ()", Rule::SpaceBeforeParen),
+                ("mul(-2,4)", Rule::Signed("-2".to_string())),
+                ("mul(2,,3)", Rule::EmptyArgument),
+                ("mul(2)", Rule::Arity(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_instructions_are_not_near_misses() {
+        let input = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+then(don't()mul(11,8)do()mul(123,0)mul(";
+        assert!(near_misses(input, &InstructionSet::puzzle()).is_empty());
+
+        // Only the unclosed one in the puzzle's example
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        assert_eq!(near_misses(input, &InstructionSet::puzzle()), [("mul(32,64", Rule::Unclosed)]);
+
+        // `don't` is read as itself, not as `do` followed by junk
+        let misses = near_misses("don't ()", &InstructionSet::puzzle());
+        assert_eq!(misses, [("don't ()", Rule::SpaceBeforeParen)]);
+    }
+
+    #[test]
+    fn rules_follow_the_instruction_set() {
+        let input = "mul(2)add(12345,1)sub(1,2,3)";
+        assert_eq!(near_misses(input, &InstructionSet::puzzle()), [("mul(2)", Rule::Arity(1))]);
+        assert_eq!(near_misses(input, &InstructionSet::extended()), [("sub(1,2,3)", Rule::Arity(3))]);
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::{ErrorKind, Read};
+
+use crate::instructions::InstructionSet;
+use crate::interpreter::{Interpreter, Totals};
+use crate::lexer;
+
+/// How much to read from the reader at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Runs the memory in `reader` through the lexer and interpreter a
+/// chunk at a time, so only a chunk (plus any instruction cut off
+/// at its end) is ever held in memory.
+pub fn evaluate<R: Read>(mut reader: R, set: &InstructionSet) -> anyhow::Result<Totals> {
+    let mut interpreter = Interpreter::new(set);
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    // Bytes read but not scanned yet, starting at `base` in the memory
+    let mut pending = Vec::<u8>::new();
+    let mut base = 0;
+
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let at_end = read == 0;
+        pending.extend_from_slice(&chunk[..read]);
+
+        let (tokens, scanned) = lexer::lex_bytes(&pending, set, base, at_end);
+        for token in &tokens {
+            interpreter.step(token)?;
+        }
+
+        pending.drain(..scanned);
+        base += scanned;
+
+        if at_end {
+            return Ok(interpreter.totals());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter;
+
+    /// Hands out `input` a few bytes at a time, cycling through read
+    /// sizes from 1 to `max`, so tokens land across read boundaries
+    struct TinyReads<'a> {
+        input: &'a [u8],
+        max: usize,
+        next: usize,
+    }
+
+    impl Read for TinyReads<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.next.min(buf.len()).min(self.input.len());
+            buf[..len].copy_from_slice(&self.input[..len]);
+            self.input = &self.input[len..];
+            self.next = self.next % self.max + 1;
+            Ok(len)
+        }
+    }
+
+    fn check(input: &str, set: &InstructionSet) {
+        let expected = interpreter::run(set, &lexer::lex(input, set)).unwrap();
+        for max in 1..=11 {
+            let reader = TinyReads { input: input.as_bytes(), max, next: 1 };
+            assert_eq!(evaluate(reader, set).unwrap(), expected, "reads of up to {} bytes", max);
+        }
+    }
+
+    #[test]
+    fn matches_lexer_across_read_boundaries() {
+        check(include_str!("day3.in"), &InstructionSet::puzzle());
+        check(include_str!("day3.in"), &InstructionSet::extended());
+    }
+
+    #[test]
+    fn handles_tokens_cut_off_at_the_end() {
+        let set = InstructionSet::extended();
+        check("xmul(2,4)don't()mul(3,", &set);
+        check("mul(2,4)add(12345,1)sub(5,2)mul(1", &set);
+        check("do()mul(2,4)mul(11,8)don", &set);
+        check("mul(2,4)mul(1234,5)mul(12,34)", &set);
+    }
+}
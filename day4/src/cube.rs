@@ -0,0 +1,74 @@
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use ndarray::{Array3, Axis};
+
+use crate::{into_matrix, search_directions, start_range, wrap_period, Boundary};
+
+/// Parses a 3D grid given as 2D layers separated by blank lines.
+/// The first axis is the layer.
+pub fn into_cube(input: &str) -> anyhow::Result<Array3<char>> {
+    let mut layers = vec![];
+    for (i, (_, lines)) in input
+        .lines()
+        .chunk_by(|l| l.trim().is_empty())
+        .into_iter()
+        .filter(|(blank, _)| !blank)
+        .enumerate()
+    {
+        let layer = into_matrix(&lines.collect::<Vec<_>>().join("\n"))
+            .with_context(|| format!("layer {}", i))?;
+        layers.push(layer);
+    }
+
+    if layers.is_empty() {
+        bail!("no layers in the input")
+    }
+
+    if let Some(i) = layers.iter().position(|l| l.dim() != layers[0].dim()) {
+        bail!("layer {} is {:?}, expected {:?}", i, layers[i].dim(), layers[0].dim())
+    }
+
+    let views = layers.iter().map(|l| l.view()).collect::<Vec<_>>();
+    Ok(ndarray::stack(Axis(0), &views)?)
+}
+
+/// Same as `count_str`, but in a cube, where words can go in any of
+/// the 26 directions.
+pub fn count_str_3d(input: &Array3<char>, pattern: &str, boundary: Boundary) -> usize {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let (layers, rows, cols) = input.dim();
+    let sizes = [layers, rows, cols];
+    if pattern.is_empty() || sizes.contains(&0) {
+        return 0;
+    }
+
+    let len = pattern.len();
+
+    let mut count = 0;
+    for direction in search_directions(&sizes, boundary) {
+        let starts = match boundary {
+            Boundary::Bounded => (0..3)
+                .map(|axis| start_range(sizes[axis], len, direction[axis]))
+                .collect::<Vec<_>>(),
+            Boundary::Wrapping if len > wrap_period(&sizes, &direction) => continue,
+            Boundary::Wrapping => sizes.iter().map(|&size| 0..size).collect(),
+        };
+
+        for start in starts.into_iter().multi_cartesian_product() {
+            let found = pattern.iter().enumerate().all(|(i, &letter)| {
+                let mut cell = [0; 3];
+                for axis in 0..3 {
+                    let index = start[axis] as i32 + direction[axis] * i as i32;
+                    cell[axis] = index.rem_euclid(sizes[axis] as i32) as usize;
+                }
+                input[cell] == letter
+            });
+
+            if found {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
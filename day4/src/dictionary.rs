@@ -0,0 +1,133 @@
+use anyhow::bail;
+use aho_corasick::AhoCorasick;
+use ndarray::Array2;
+
+use crate::WordMatch;
+
+/// One row, column or diagonal of the grid, read in `direction`
+struct GridLine {
+    text: String,
+
+    /// Byte offset of each letter in `text`
+    offsets: Vec<usize>,
+
+    /// Grid cell of each letter in `text`
+    cells: Vec<(usize, usize)>,
+
+    direction: (i32, i32),
+}
+
+/// Reads the grid as lines in the four directions. Every straight
+/// word in the grid is in one of these, either forwards or backwards.
+fn grid_lines(input: &Array2<char>) -> Vec<GridLine> {
+    let (rows, cols) = input.dim();
+    if rows == 0 || cols == 0 {
+        return vec![];
+    }
+
+    // Where each line starts for each direction
+    let rows_start = (0..rows).map(|r| (r, 0)).collect::<Vec<_>>();
+    let cols_start = (0..cols).map(|c| (0, c)).collect::<Vec<_>>();
+    let diagonals_start = (0..rows)
+        .rev()
+        .map(|r| (r, 0))
+        .chain((1..cols).map(|c| (0, c)))
+        .collect::<Vec<_>>();
+    let anti_diagonals_start = (0..cols)
+        .map(|c| (0, c))
+        .chain((1..rows).map(|r| (r, cols - 1)))
+        .collect::<Vec<_>>();
+
+    let starts = [
+        ((0, 1), rows_start),
+        ((1, 0), cols_start),
+        ((1, 1), diagonals_start),
+        ((1, -1), anti_diagonals_start),
+    ];
+
+    let mut lines = vec![];
+    for (direction, starts) in starts {
+        for (row, col) in starts {
+            let mut line = GridLine {
+                text: String::new(),
+                offsets: vec![],
+                cells: vec![],
+                direction,
+            };
+
+            let (mut r, mut c) = (row as i32, col as i32);
+            while r >= 0 && c >= 0 && r < rows as i32 && c < cols as i32 {
+                let cell = (r as usize, c as usize);
+                line.offsets.push(line.text.len());
+                line.cells.push(cell);
+                line.text.push(input[cell]);
+                (r, c) = (r + direction.0, c + direction.1);
+            }
+
+            lines.push(line);
+        }
+    }
+
+    lines
+}
+
+/// Finds every occurence of each of the `words` in any of the
+/// eight directions, scanning each line of the grid once for all
+/// words together. The matches come back in the same order as
+/// `words`.
+pub fn search_words(input: &Array2<char>, words: &[&str]) -> anyhow::Result<Vec<Vec<WordMatch>>> {
+    // Each word is searched for as itself and reversed (for the
+    // opposite direction). Palindromes give the same string twice,
+    // which must still count as two matches
+    let mut patterns = Vec::<String>::new();
+    let mut owners = Vec::<Vec<(usize, bool)>>::new();
+    for (word_index, word) in words.iter().enumerate() {
+        if word.is_empty() {
+            bail!("cannot search for an empty word")
+        }
+
+        for reversed in [false, true] {
+            let pattern = if reversed {
+                word.chars().rev().collect::<String>()
+            } else {
+                word.to_string()
+            };
+
+            let pattern_index = match patterns.iter().position(|p| *p == pattern) {
+                Some(i) => i,
+                None => {
+                    patterns.push(pattern);
+                    owners.push(vec![]);
+                    patterns.len() - 1
+                }
+            };
+            owners[pattern_index].push((word_index, reversed));
+        }
+    }
+
+    let automaton = AhoCorasick::new(&patterns)?;
+
+    let mut matches = vec![Vec::<WordMatch>::new(); words.len()];
+    for line in grid_lines(input) {
+        for found in automaton.find_overlapping_iter(&line.text) {
+            let first = line.offsets.binary_search(&found.start()).unwrap();
+            let last = line.offsets.partition_point(|&o| o < found.end()) - 1;
+
+            for &(word_index, reversed) in &owners[found.pattern().as_usize()] {
+                let (start, direction) = if reversed {
+                    (line.cells[last], (-line.direction.0, -line.direction.1))
+                } else {
+                    (line.cells[first], line.direction)
+                };
+
+                matches[word_index].push(WordMatch {
+                    row: start.0,
+                    col: start.1,
+                    direction,
+                });
+            }
+        }
+    }
+
+    Ok(matches)
+}
@@ -1,43 +1,121 @@
 use anyhow::bail;
 use itertools::Itertools;
 use ndarray::Array2;
+use unicode_segmentation::UnicodeSegmentation;
+
+mod cube;
+mod dictionary;
+mod stencil;
+
+use stencil::{Stencil, StencilMatch, Symmetry};
+
+/// All the unit steps in a grid with `dims` dimensions, i.e. every
+/// combination of -1, 0 and 1 per axis except standing still. That's
+/// the 8 straights and diagonals in 2D, and 26 directions in 3D.
+fn directions(dims: usize) -> Vec<Vec<i32>> {
+    (0..dims)
+        .map(|_| [-1, 0, 1])
+        .multi_cartesian_product()
+        .filter(|d| d.iter().any(|&step| step != 0))
+        .collect()
+}
 
-const DIRECTIONS : &[(i32, i32)] = &[
-    // Straights
-    (0, -1),
-    (0, 1),
-    (1, 0),
-    (-1, 0),
+/// `directions(2)` as `(row, col)` steps
+fn directions_2d() -> Vec<(i32, i32)> {
+    directions(2).into_iter().map(|d| (d[0], d[1])).collect()
+}
 
-    // Diagonals
-    (1, 1),
-    (-1, 1),
-    (1, -1),
-    (-1, -1),
-];
+/// What happens to a word that runs off the edge of the grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Boundary {
+    /// The word doesn't fit, as in the puzzle
+    Bounded,
+    /// The word carries on from the opposite edge
+    Wrapping,
+}
 
-fn into_matrix(input: &str) -> anyhow::Result<Array2<char>> {
+/// Directions to search in a grid of `sizes`. When wrapping, steps
+/// are taken modulo the size of each axis, so directions that end up
+/// the same (e.g. up and down in a grid two rows high) would read the
+/// same cells. Only the first of those is kept.
+fn search_directions(sizes: &[usize], boundary: Boundary) -> Vec<Vec<i32>> {
+    let all = directions(sizes.len());
+    match boundary {
+        Boundary::Bounded => all,
+        Boundary::Wrapping => all
+            .into_iter()
+            .filter(|d| d.iter().zip(sizes).any(|(&step, &size)| step.rem_euclid(size as i32) != 0))
+            .unique_by(|d| {
+                d.iter()
+                    .zip(sizes)
+                    .map(|(&step, &size)| step.rem_euclid(size as i32))
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    }
+}
+
+/// Number of steps in `direction` before a walk on a wrapping grid
+/// of `sizes` comes back to where it started.
+fn wrap_period(sizes: &[usize], direction: &[i32]) -> usize {
+    let gcd = |mut a: usize, mut b: usize| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+
+    sizes
+        .iter()
+        .zip(direction)
+        .filter(|(&size, &step)| step.rem_euclid(size as i32) != 0)
+        .fold(1, |period, (&size, _)| period / gcd(period, size) * size)
+}
+
+/// Splits `input` into a grid, using `split` to break each line
+/// into cells. Line endings (`\n` or `\r\n`), trailing whitespace
+/// and trailing blank lines are ignored, and empty input gives an
+/// empty grid.
+fn into_grid<T>(input: &str, split: impl Fn(&str) -> Vec<T>) -> anyhow::Result<Array2<T>> {
 
     // Split on new lines to get the vector
-    let lines = input
+    let mut lines = input
         .lines()
-        .map(|l| l.chars().collect::<Vec<_>>())
+        .map(|l| l.trim_end())
+        .collect::<Vec<_>>();
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    let lines = lines
+        .into_iter()
+        .map(split)
         .collect::<Vec<_>>();
 
     // Make sure all rows have the same size
     let col_size = lines.len();
-    let row_size = lines[0].clone().len();
+    let row_size = lines.first().map_or(0, |l| l.len());
 
-    if !lines.iter().map(|l| l.len()).all(|s| s == row_size) {
-        bail!("not all input rows have the same size")
+    if let Some(row) = lines.iter().position(|l| l.len() != row_size) {
+        bail!("row {} has {} cells, but row 1 has {}", row + 1, lines[row].len(), row_size)
     }
 
     let lines_flattened = lines.into_iter().flatten().collect::<Vec<_>>();
-    let lines_array = Array2::<char>::from_shape_vec((col_size, row_size),lines_flattened)?;
+    let lines_array = Array2::<T>::from_shape_vec((col_size, row_size),lines_flattened)?;
 
     Ok(lines_array)
 }
 
+fn into_matrix(input: &str) -> anyhow::Result<Array2<char>> {
+    into_grid(input, |l| l.chars().collect())
+}
+
+/// Same as `into_matrix`, but each cell is an extended grapheme
+/// cluster, so accented letters and combining marks stay together.
+fn into_grapheme_matrix(input: &str) -> anyhow::Result<Array2<String>> {
+    into_grid(input, |l| l.graphemes(true).map(String::from).collect())
+}
+
 /// Finds all the possible positions by walking in the
 /// given `direction`` from (0,0) (inclusively) when
 /// taking `steps` steps.
@@ -60,10 +138,13 @@ fn direction_walk(direction: &(i32, i32), steps: usize) -> Vec<(i32, i32)> {
 
 /// Counts the number of occurences of the given `pattern`
 /// in the `input` array string.
-fn count_str(input: &Array2<char>, pattern: &str) -> usize {
+///
+/// This is the original version, which allocates for every cell.
+/// It's kept as the baseline for `--bench`.
+fn count_str_naive(input: &Array2<char>, pattern: &str) -> usize {
 
     // create the right offsets for each direction
-    let directions = DIRECTIONS
+    let directions = directions_2d()
         .iter()
         .map(|d| direction_walk(d, pattern.len()))
         .collect::<Vec<_>>();
@@ -96,8 +177,141 @@ fn count_str(input: &Array2<char>, pattern: &str) -> usize {
     count
 }
 
+/// Converts the grid to bytes for the fast counting functions.
+/// Fails if any letter isn't ASCII.
+fn into_bytes(input: &Array2<char>) -> anyhow::Result<Array2<u8>> {
+    if let Some(((row, col), c)) = input.indexed_iter().find(|(_, c)| !c.is_ascii()) {
+        bail!("non ASCII letter {:?} at ({}, {})", c, row, col)
+    }
+
+    Ok(input.mapv(|c| c as u8))
+}
+
+/// Range of start indices along one axis of size `size` for which
+/// a walk of `len` cells with step `delta` stays in the grid.
+fn start_range(size: usize, len: usize, delta: i32) -> std::ops::Range<usize> {
+    match delta {
+        0 => 0..size,
+        d if d > 0 => 0..(size + 1).saturating_sub(len),
+        _ => (len - 1).min(size)..size,
+    }
+}
+
+/// Calls `found` with the start and direction of every occurence
+/// of `pattern` in the `input` grid.
+///
+/// Bounds are worked out once per direction rather than for each
+/// cell, and each candidate stops at the first mismatching letter.
+///
+/// With `Boundary::Wrapping` every cell is a possible start, and
+/// words too long for the loop they walk along are skipped, as they
+/// would wrap onto themselves and read the same cell twice.
+fn for_each_match<T: PartialEq>(
+    input: &Array2<T>,
+    pattern: &[T],
+    boundary: Boundary,
+    mut found: impl FnMut(usize, usize, (i32, i32)),
+) {
+    let (rows, cols) = input.dim();
+    if pattern.is_empty() || rows == 0 || cols == 0 {
+        return;
+    }
+
+    let len = pattern.len();
+    let wrap = |index: i32, size: usize| match boundary {
+        Boundary::Bounded => index as usize,
+        Boundary::Wrapping => index.rem_euclid(size as i32) as usize,
+    };
+
+    for direction in search_directions(&[rows, cols], boundary) {
+        let (dr, dc) = (direction[0], direction[1]);
+        let (row_starts, col_starts) = match boundary {
+            Boundary::Bounded => (start_range(rows, len, dr), start_range(cols, len, dc)),
+            Boundary::Wrapping if len > wrap_period(&[rows, cols], &[dr, dc]) => continue,
+            Boundary::Wrapping => (0..rows, 0..cols),
+        };
+
+        for row in row_starts {
+            for col in col_starts.clone() {
+                let matches = pattern.iter().enumerate().all(|(i, letter)| {
+                    let r = wrap(row as i32 + dr * i as i32, rows);
+                    let c = wrap(col as i32 + dc * i as i32, cols);
+                    input[(r, c)] == *letter
+                });
+
+                if matches {
+                    found(row, col, (dr, dc));
+                }
+            }
+        }
+    }
+}
+
+/// Counts the number of occurences of the given `pattern`
+/// in the `input` grid. The cells can be anything comparable,
+/// e.g. bytes, or grapheme clusters for Unicode grids.
+fn count_str<T: PartialEq>(input: &Array2<T>, pattern: &[T], boundary: Boundary) -> usize {
+    let mut count = 0;
+    for_each_match(input, pattern, boundary, |_, _, _| count += 1);
+    count
+}
+
+/// A word found in the grid, read from `(row, col)` in `direction`
+#[derive(Clone, Debug)]
+struct WordMatch {
+    row: usize,
+    col: usize,
+    direction: (i32, i32),
+}
+
+impl WordMatch {
+    /// Grid cells covered by a word of `len` letters, wrapping round
+    /// the edges of a grid of `dim` for wrapping matches
+    fn cells(&self, len: usize, dim: (usize, usize)) -> Vec<(usize, usize)> {
+        let (rows, cols) = (dim.0 as i32, dim.1 as i32);
+        direction_walk(&self.direction, len)
+            .into_iter()
+            .map(|(r, c)| {
+                let r = (self.row as i32 + r).rem_euclid(rows);
+                let c = (self.col as i32 + c).rem_euclid(cols);
+                (r as usize, c as usize)
+            })
+            .collect()
+    }
+}
+
+/// Same as `count_str`, but returns where each occurence starts
+/// and which way it reads.
+fn find_str(input: &Array2<char>, pattern: &str, boundary: Boundary) -> Vec<WordMatch> {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+
+    let mut matches = vec![];
+    for_each_match(input, &pattern, boundary, |row, col, direction| {
+        matches.push(WordMatch { row, col, direction });
+    });
+
+    matches
+}
+
+/// Draws the grid with every letter outside of `cells` replaced
+/// by `.`, like the puzzle's examples.
+fn render_matches(input: &Array2<char>, cells: impl IntoIterator<Item = (usize, usize)>) -> String {
+    let mut shown = Array2::from_elem(input.dim(), '.');
+    for cell in cells {
+        shown[cell] = input[cell];
+    }
+
+    shown
+        .rows()
+        .into_iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
 // Cannot be bothered making this generic
-fn count_mas_cross(input: &Array2<char>) -> usize {
+//
+// Original version, kept as the baseline for `--bench`
+fn count_mas_cross_naive(input: &Array2<char>) -> usize {
 
     let positions = &[
         (-1, -1),
@@ -134,7 +348,7 @@ fn count_mas_cross(input: &Array2<char>) -> usize {
             .collect::<Vec<_>>();
 
         // should be equal to MAS or SAM
-        let allowed_patterns = vec![
+        let allowed_patterns = [
             "MAS".chars().collect::<Vec<_>>(),
             "SAM".chars().collect::<Vec<_>>(),
         ];
@@ -148,19 +362,225 @@ fn count_mas_cross(input: &Array2<char>) -> usize {
     count
 }
 
+/// Counts the `MAS` crosses in the grid, looking at each
+/// possible centre `A` in place. Works on `char` grids, or on
+/// bytes for ASCII grids.
+fn count_mas_cross<T: PartialEq + From<u8>>(input: &Array2<T>) -> usize {
+    let (rows, cols) = input.dim();
+    if rows < 3 || cols < 3 {
+        return 0;
+    }
+
+    let (m, a, s) = (T::from(b'M'), T::from(b'A'), T::from(b'S'));
+    let is_mas = |x: &T, y: &T| (*x == m && *y == s) || (*x == s && *y == m);
+
+    let mut count = 0;
+    for row in 1..rows - 1 {
+        for col in 1..cols - 1 {
+            if input[(row, col)] == a
+                && is_mas(&input[(row - 1, col - 1)], &input[(row + 1, col + 1)])
+                && is_mas(&input[(row + 1, col - 1)], &input[(row - 1, col + 1)])
+            {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+/// Times `f` over `runs` runs and returns the average in microseconds
+fn time_runs(runs: u32, mut f: impl FnMut() -> usize) -> u128 {
+    let start = std::time::Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(f());
+    }
+
+    start.elapsed().as_micros() / runs as u128
+}
+
+/// Compares the original counting functions with the faster ones
+fn run_bench(text_array: &Array2<char>, byte_array: &Array2<u8>) {
+    let runs = 20;
+
+    assert_eq!(count_str_naive(text_array, "XMAS"), count_str(byte_array, b"XMAS", Boundary::Bounded));
+    assert_eq!(count_mas_cross_naive(text_array), count_mas_cross(byte_array));
+
+    let naive = time_runs(runs, || count_str_naive(text_array, "XMAS"));
+    let fast = time_runs(runs, || count_str(byte_array, b"XMAS", Boundary::Bounded));
+    println!("count_str: {}us -> {}us ({:.1}x)", naive, fast, naive as f64 / fast.max(1) as f64);
+
+    let naive = time_runs(runs, || count_mas_cross_naive(text_array));
+    let fast = time_runs(runs, || count_mas_cross(byte_array));
+    println!("count_mas_cross: {}us -> {}us ({:.1}x)", naive, fast, naive as f64 / fast.max(1) as f64);
+}
+
+/// Finds the stencils given with `--stencil <pattern>`, and the
+/// straight and diagonal stencils of `--word-stencil <word>` (several
+/// of each are allowed), matching them under `--symmetry none|rotations|all`.
+fn find_custom_stencils(input: &Array2<char>, args: &[String]) -> anyhow::Result<Option<Vec<StencilMatch>>> {
+    let mut stencils = vec![];
+    let mut symmetry = Symmetry::All;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stencil" => {
+                let Some(pattern) = args.next() else {
+                    bail!("--stencil needs a pattern")
+                };
+                stencils.push(Stencil::parse(pattern)?);
+            },
+            "--word-stencil" => {
+                let Some(word) = args.next() else {
+                    bail!("--word-stencil needs a word")
+                };
+                stencils.extend(stencil::word_stencils(word));
+            },
+            "--symmetry" => {
+                symmetry = match args.next().map(String::as_str) {
+                    Some("none") => Symmetry::None,
+                    Some("rotations") => Symmetry::Rotations,
+                    Some("all") => Symmetry::All,
+                    _ => bail!("--symmetry needs one of none, rotations, all"),
+                };
+            },
+            _ => bail!("unknown argument: {}", arg),
+        }
+    }
+
+    if stencils.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(stencil::find_stencils(input, &stencils, symmetry)))
+}
+
 fn try_main() -> anyhow::Result<()> {
 
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    // Pass `--bench` to time the counting functions
+    let bench = args.iter().any(|a| a == "--bench");
+    args.retain(|a| a != "--bench");
+
+    // Pass `--wrap` to let words run across the edges in part one
+    let wrapping = args.iter().any(|a| a == "--wrap");
+    args.retain(|a| a != "--wrap");
+
+    // Pass `--cube` to read blank line separated layers of a 3D grid
+    let cube = args.iter().any(|a| a == "--cube");
+    args.retain(|a| a != "--cube");
+
+    // Pass `--graphemes <word>` to search a Unicode grid for `word`,
+    // treating each grapheme cluster as one letter
+    let graphemes = match args.iter().position(|a| a == "--graphemes") {
+        Some(i) => {
+            let Some(word) = args.get(i + 1).cloned() else {
+                bail!("--graphemes needs a word")
+            };
+            args.drain(i..i + 2);
+            Some(word)
+        },
+        None => None,
+    };
+
+    // Pass `--show` to print every match and the grid with
+    // everything else blanked out
+    let show = args.iter().any(|a| a == "--show");
+    args.retain(|a| a != "--show");
+
+    // `--input <file>` reads another grid, e.g. `part_one_test.in`
+    let input = match args.iter().position(|a| a == "--input") {
+        Some(i) => {
+            let Some(path) = args.get(i + 1) else {
+                bail!("--input needs a file")
+            };
+            let input = std::fs::read_to_string(path)?;
+            args.drain(i..i + 2);
+            input
+        },
+        None => include_str!("day4.in").to_string(),
+    };
+    let boundary = if wrapping { Boundary::Wrapping } else { Boundary::Bounded };
+
+    if let Some(word) = graphemes {
+        let grid = into_grapheme_matrix(&input)?;
+        let pattern = word.graphemes(true).map(String::from).collect::<Vec<_>>();
+        println!("Day4 grapheme matches: {}", count_str(&grid, &pattern, boundary));
+        return Ok(());
+    }
+
+    if cube {
+        let cube = cube::into_cube(&input)?;
+        println!("Day4 cube matches: {}", cube::count_str_3d(&cube, "XMAS", boundary));
+        return Ok(());
+    }
+
+    let text_array =  into_matrix(&input)?;
+
+    // The benchmark runs the fast functions on bytes, so it needs
+    // an ASCII grid
+    if bench {
+        run_bench(&text_array, &into_bytes(&text_array)?);
+        return Ok(());
+    }
+
+    // `--words <a,b,...>` searches for all the given words at once
+    if let Some(i) = args.iter().position(|a| a == "--words") {
+        let Some(words) = args.get(i + 1) else {
+            bail!("--words needs a comma separated list")
+        };
+        let words = words.split(',').collect::<Vec<_>>();
+        let matches = dictionary::search_words(&text_array, &words)?;
+
+        for (word, word_matches) in words.iter().zip(&matches) {
+            println!("{}: {}", word, word_matches.len());
+            if show {
+                for m in word_matches {
+                    println!("    ({}, {}) {:?}", m.row, m.col, m.direction);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(matches) = find_custom_stencils(&text_array, &args)? {
+        if show {
+            for m in &matches {
+                println!("({}, {}) {}", m.row, m.col, m.orientation);
+            }
+            println!("{}", render_matches(&text_array, matches.iter().flat_map(|m| m.cells.clone())));
+        }
+        println!("Day4 stencil matches: {}", matches.len());
+        return Ok(());
+    }
+
     // Part one: find all 'XMAS' str in the input
-    let input = include_str!("day4.in");
-    let text_array =  into_matrix(input)?;
-    let count = count_str(&text_array, "XMAS");
+    let count = count_str(&text_array, &['X', 'M', 'A', 'S'], boundary);
     println!("Day4 part one: {:?}", count);
 
+    if show {
+        let matches = find_str(&text_array, "XMAS", boundary);
+        for m in &matches {
+            println!("({}, {}) {:?}", m.row, m.col, m.direction);
+        }
+        println!("{}", render_matches(&text_array, matches.iter().flat_map(|m| m.cells(4, text_array.dim()))));
+    }
 
     // Part two: find all `MAS` in the shape of an X
     // in the data
     let count = count_mas_cross(&text_array);
     println!("Day4 part two: {:?}", count);
+
+    if show {
+        let stencils = [stencil::mas_cross_stencil()];
+        let matches = stencil::find_stencils(&text_array, &stencils, Symmetry::Rotations);
+        for m in &matches {
+            println!("({}, {}) {}", m.row, m.col, m.orientation);
+        }
+        println!("{}", render_matches(&text_array, matches.iter().flat_map(|m| m.cells.clone())));
+    }
     Ok(())
 }
 
@@ -175,3 +595,217 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    /// Small grids made of the puzzle's letters, so words actually
+    /// turn up in them
+    fn small_grid(rows: u8, letters: &[u8]) -> Array2<char> {
+        let rows = 1 + rows as usize % 6;
+        let cols = letters.len() / rows;
+        let cells = letters
+            .iter()
+            .take(rows * cols)
+            .map(|&l| ['X', 'M', 'A', 'S'][l as usize % 4])
+            .collect::<Vec<_>>();
+
+        Array2::from_shape_vec((rows, cols), cells).unwrap()
+    }
+
+    fn xmas() -> Vec<char> {
+        "XMAS".chars().collect()
+    }
+
+    #[test]
+    fn part_one_example() {
+        let grid = into_matrix(include_str!("part_one_test.in")).unwrap();
+        let stencils = stencil::word_stencils("XMAS");
+
+        assert_eq!(count_str(&grid, &xmas(), Boundary::Bounded), 18);
+        assert_eq!(count_str_naive(&grid, "XMAS"), 18);
+        assert_eq!(stencil::find_stencils(&grid, &stencils, Symmetry::Rotations).len(), 18);
+    }
+
+    #[test]
+    fn part_two_example() {
+        let grid = into_matrix(include_str!("part_two_test.in")).unwrap();
+        let stencils = [stencil::mas_cross_stencil()];
+
+        assert_eq!(count_mas_cross(&grid), 9);
+        assert_eq!(count_mas_cross(&into_bytes(&grid).unwrap()), 9);
+        assert_eq!(count_mas_cross_naive(&grid), 9);
+        assert_eq!(stencil::find_stencils(&grid, &stencils, Symmetry::Rotations).len(), 9);
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let grid = into_matrix(include_str!("day4.in")).unwrap();
+        let word = stencil::word_stencils("XMAS");
+        let cross = [stencil::mas_cross_stencil()];
+
+        assert_eq!(count_str(&grid, &xmas(), Boundary::Bounded), 2462);
+        assert_eq!(stencil::find_stencils(&grid, &word, Symmetry::Rotations).len(), 2462);
+        assert_eq!(stencil::find_stencils(&grid, &cross, Symmetry::Rotations).len(), 1877);
+    }
+
+    #[quickcheck]
+    fn word_stencils_match_count_str(rows: u8, letters: Vec<u8>) -> bool {
+        let grid = small_grid(rows, &letters);
+        let stencils = stencil::word_stencils("XMAS");
+        stencil::find_stencils(&grid, &stencils, Symmetry::Rotations).len() == count_str(&grid, &xmas(), Boundary::Bounded)
+    }
+
+    #[quickcheck]
+    fn cross_stencil_matches_count_mas_cross(rows: u8, letters: Vec<u8>) -> bool {
+        let grid = small_grid(rows, &letters);
+        let stencils = [stencil::mas_cross_stencil()];
+        stencil::find_stencils(&grid, &stencils, Symmetry::Rotations).len() == count_mas_cross(&into_bytes(&grid).unwrap())
+    }
+
+    /// `search_words`, checked word by word against `count_str`
+    fn words_match_count_str(grid: &Array2<char>, words: &[&str]) -> bool {
+        let matches = dictionary::search_words(grid, words).unwrap();
+        words.iter().zip(&matches).all(|(word, found)| {
+            let pattern = word.chars().collect::<Vec<_>>();
+            found.len() == count_str(grid, &pattern, Boundary::Bounded)
+        })
+    }
+
+    #[test]
+    fn dictionary_palindromes() {
+        for input in [include_str!("part_one_test.in"), include_str!("day4.in")] {
+            let grid = into_matrix(input).unwrap();
+            assert!(words_match_count_str(&grid, &["SAS"]));
+            assert!(words_match_count_str(&grid, &["A"]));
+            assert!(words_match_count_str(&grid, &["MAM", "A", "SAS", "XMAS"]));
+        }
+    }
+
+    #[test]
+    fn dictionary_reversed_pairs() {
+        for input in [include_str!("part_one_test.in"), include_str!("day4.in")] {
+            let grid = into_matrix(input).unwrap();
+            assert!(words_match_count_str(&grid, &["XMAS", "SAMX"]));
+            assert!(words_match_count_str(&grid, &["SAMX", "XMAS", "XMAS"]));
+        }
+    }
+
+    #[quickcheck]
+    fn dictionary_matches_count_str(rows: u8, letters: Vec<u8>, words: Vec<Vec<u8>>) -> bool {
+        let grid = small_grid(rows, &letters);
+
+        // Short words over a couple of letters, so palindromes and
+        // words that are each other's reverse come up often
+        let words = words
+            .iter()
+            .take(4)
+            .map(|w| {
+                let len = 1 + w.len() % 4;
+                w.iter().take(len).map(|&l| ['X', 'M'][l as usize % 2]).collect::<String>()
+            })
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+
+        words_match_count_str(&grid, &words)
+    }
+
+    #[test]
+    fn line_endings_and_trailing_whitespace() {
+        let grid = into_matrix("XMAS  \r\nSAMX\t\r\n\r\n\n").unwrap();
+        assert_eq!(grid, into_matrix("XMAS\nSAMX").unwrap());
+        assert_eq!(grid.dim(), (2, 4));
+    }
+
+    #[test]
+    fn ragged_rows_are_an_error() {
+        let error = into_matrix("XMAS\nXMAS\nSAM\n").unwrap_err();
+        assert_eq!(error.to_string(), "row 3 has 3 cells, but row 1 has 4");
+    }
+
+    #[test]
+    fn combining_marks_stay_in_one_cell() {
+        // `A` followed by a combining acute accent
+        let input = "XMA\u{301}S\nSA\u{301}MX";
+        let grid = into_grapheme_matrix(input).unwrap();
+        assert_eq!(grid.dim(), (2, 4));
+        assert_eq!(grid[(0, 2)], "A\u{301}");
+        assert_eq!(into_matrix(input).unwrap().dim(), (2, 5));
+
+        let word = "XMA\u{301}S".graphemes(true).map(String::from).collect::<Vec<_>>();
+        assert_eq!(count_str(&grid, &word, Boundary::Bounded), 2);
+    }
+
+    #[test]
+    fn empty_grids_have_no_matches() {
+        let grid = into_matrix("").unwrap();
+        for boundary in [Boundary::Bounded, Boundary::Wrapping] {
+            assert_eq!(count_str(&grid, &xmas(), boundary), 0);
+            assert_eq!(cube::count_str_3d(&ndarray::Array3::from_elem((2, 0, 3), 'X'), "XMAS", boundary), 0);
+        }
+    }
+
+    #[test]
+    fn wrapping_words_cross_the_edge() {
+        let grid = into_matrix("MASX\nOOOO\nOOOO").unwrap();
+        assert_eq!(count_str(&grid, &xmas(), Boundary::Bounded), 0);
+        assert_eq!(count_str(&grid, &xmas(), Boundary::Wrapping), 1);
+
+        let matches = find_str(&grid, "XMAS", Boundary::Wrapping);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].cells(4, grid.dim()), [(0, 3), (0, 0), (0, 1), (0, 2)]);
+
+        // Down from the bottom row back round to the top
+        let grid = into_matrix("AO\nSO\nXO\nMO").unwrap();
+        assert_eq!(count_str(&grid, &xmas(), Boundary::Wrapping), 1);
+    }
+
+    #[test]
+    fn wrapping_words_dont_read_a_cell_twice() {
+        // Going round the row gives `XMAXMA...`, but a 4 letter word
+        // would have to use the `X` twice
+        let grid = into_matrix("XMA").unwrap();
+        let pattern = |word: &str| word.chars().collect::<Vec<_>>();
+        assert_eq!(count_str(&grid, &pattern("XMAX"), Boundary::Wrapping), 0);
+        assert_eq!(count_str(&grid, &pattern("MAX"), Boundary::Wrapping), 1);
+
+        let cube = cube::into_cube("XMA").unwrap();
+        assert_eq!(cube::count_str_3d(&cube, "XMAX", Boundary::Wrapping), 0);
+        assert_eq!(cube::count_str_3d(&cube, "MAX", Boundary::Wrapping), 1);
+    }
+
+    #[test]
+    fn cube_diagonals() {
+        // XMAS along (1, 1, 1) from the corner at (0, 0, 0), and along
+        // (1, 1, -1) from (0, 0, 3)
+        let layers = [
+            "X..X\n....\n....\n....",
+            "....\n.MM.\n....\n....",
+            "....\n....\n.AA.\n....",
+            "....\n....\n....\nS..S",
+        ];
+        let cube = cube::into_cube(&layers.join("\n\n")).unwrap();
+        assert_eq!(cube.dim(), (4, 4, 4));
+
+        assert_eq!(cube::count_str_3d(&cube, "XMAS", Boundary::Bounded), 2);
+        assert_eq!(cube::count_str_3d(&cube, "XMAS", Boundary::Wrapping), 2);
+        assert_eq!(cube::count_str_3d(&cube, "SAMX", Boundary::Bounded), 2);
+    }
+
+    #[quickcheck]
+    fn find_str_matches_count_str(rows: u8, letters: Vec<u8>, wrapping: bool) -> bool {
+        let grid = small_grid(rows, &letters);
+        let boundary = if wrapping { Boundary::Wrapping } else { Boundary::Bounded };
+
+        // Every match must read the word from the cells it reports
+        let matches = find_str(&grid, "XMAS", boundary);
+        let cells_spell_word = matches.iter().all(|m| {
+            m.cells(4, grid.dim()).iter().map(|&cell| grid[cell]).collect::<Vec<_>>() == xmas()
+        });
+
+        cells_spell_word && matches.len() == count_str(&grid, &xmas(), boundary)
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::bail;
+use itertools::Itertools;
+use ndarray::{s, Array2};
+
+/// Which transformed copies of a stencil should also be matched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    /// Only the stencil as written
+    None,
+    /// The four 90 degree rotations
+    Rotations,
+    /// The four rotations and their mirror images
+    All,
+}
+
+/// How a stencil variant was made from the original: turned
+/// clockwise `quarter_turns` times, then maybe mirrored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Orientation {
+    pub quarter_turns: usize,
+    pub reflected: bool,
+}
+
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} deg", self.quarter_turns * 90)?;
+        if self.reflected {
+            write!(f, ", mirrored")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a stencil matched: its top left corner, the variant that
+/// matched, and the grid cells its letters covered
+#[derive(Clone, Debug)]
+pub struct StencilMatch {
+    pub row: usize,
+    pub col: usize,
+    pub orientation: Orientation,
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// A small 2D pattern to look for in the grid. `None` cells are
+/// wildcards and match any letter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stencil {
+    cells: Array2<Option<char>>,
+}
+
+impl Stencil {
+    /// Parses a stencil with rows separated by `/` or new lines,
+    /// and `.` as the wildcard, e.g. `M.S/.A./M.S`.
+    pub fn parse(pattern: &str) -> anyhow::Result<Stencil> {
+        let rows = pattern
+            .split(['/', '\n'])
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+            .map(|r| r.chars().map(|c| (c != '.').then_some(c)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        if rows.is_empty() {
+            bail!("empty stencil")
+        }
+
+        let width = rows[0].len();
+        if let Some(row) = rows.iter().position(|r| r.len() != width) {
+            bail!("stencil row {} has {} cells, expected {}", row + 1, rows[row].len(), width)
+        }
+
+        let height = rows.len();
+        let cells = Array2::from_shape_vec((height, width), rows.into_iter().flatten().collect())?;
+        Ok(Stencil { cells })
+    }
+
+    /// A stencil for `word` written left to right
+    pub fn line(word: &str) -> Stencil {
+        let letters = word.chars().map(Some).collect::<Vec<_>>();
+        Stencil {
+            cells: Array2::from_shape_vec((1, letters.len()), letters).unwrap(),
+        }
+    }
+
+    /// A stencil for `word` written from the top left corner down
+    /// to the bottom right one
+    pub fn diagonal(word: &str) -> Stencil {
+        let size = word.chars().count();
+        let mut cells = Array2::from_elem((size, size), None);
+        for (i, c) in word.chars().enumerate() {
+            cells[(i, i)] = Some(c);
+        }
+
+        Stencil { cells }
+    }
+
+    /// The stencil turned 90 degrees clockwise
+    fn rotate(&self) -> Stencil {
+        Stencil {
+            cells: self.cells.t().slice(s![.., ..;-1]).to_owned(),
+        }
+    }
+
+    /// The stencil mirrored left to right
+    fn reflect(&self) -> Stencil {
+        Stencil {
+            cells: self.cells.slice(s![.., ..;-1]).to_owned(),
+        }
+    }
+
+    /// All the distinct variants of this stencil under `symmetry`.
+    /// Symmetric stencils (e.g. palindromes) yield fewer variants,
+    /// so the same cells are never counted twice. Each variant comes
+    /// with how it was made from the original.
+    pub fn variants(&self, symmetry: Symmetry) -> Vec<(Orientation, Stencil)> {
+        let original = Orientation { quarter_turns: 0, reflected: false };
+        let mut variants = vec![(original, self.clone())];
+
+        if symmetry != Symmetry::None {
+            for quarter_turns in 1..4 {
+                let next = variants.last().unwrap().1.rotate();
+                variants.push((Orientation { quarter_turns, reflected: false }, next));
+            }
+        }
+
+        if symmetry == Symmetry::All {
+            let reflected = variants
+                .iter()
+                .map(|(o, v)| (Orientation { reflected: true, ..*o }, v.reflect()))
+                .collect::<Vec<_>>();
+            variants.extend(reflected);
+        }
+
+        let mut distinct: Vec<(Orientation, Stencil)> = vec![];
+        for (orientation, variant) in variants {
+            if !distinct.iter().any(|(_, v)| *v == variant) {
+                distinct.push((orientation, variant));
+            }
+        }
+
+        distinct
+    }
+
+    /// Whether the stencil matches with its top left corner at `(row, col)`
+    pub fn matches_at(&self, input: &Array2<char>, row: usize, col: usize) -> bool {
+        let (height, width) = self.cells.dim();
+        let (rows, cols) = input.dim();
+        if row + height > rows || col + width > cols {
+            return false;
+        }
+
+        self.cells
+            .indexed_iter()
+            .all(|((r, c), cell)| cell.is_none_or(|letter| input[(row + r, col + c)] == letter))
+    }
+
+    /// Top left corners of every match of this stencil alone
+    pub fn find(&self, input: &Array2<char>) -> Vec<(usize, usize)> {
+        let (rows, cols) = input.dim();
+        (0..rows)
+            .cartesian_product(0..cols)
+            .filter(|&(row, col)| self.matches_at(input, row, col))
+            .collect()
+    }
+}
+
+/// Every match of every distinct variant of the `stencils`. Variants
+/// shared between stencils are only matched once.
+pub fn find_stencils(input: &Array2<char>, stencils: &[Stencil], symmetry: Symmetry) -> Vec<StencilMatch> {
+    let mut variants: Vec<(Orientation, Stencil)> = vec![];
+    for (orientation, variant) in stencils.iter().flat_map(|s| s.variants(symmetry)) {
+        if !variants.iter().any(|(_, v)| *v == variant) {
+            variants.push((orientation, variant));
+        }
+    }
+
+    let mut matches = vec![];
+    for (orientation, variant) in &variants {
+        for (row, col) in variant.find(input) {
+            let cells = variant
+                .cells
+                .indexed_iter()
+                .filter(|(_, cell)| cell.is_some())
+                .map(|((r, c), _)| (row + r, col + c))
+                .collect();
+            matches.push(StencilMatch { row, col, orientation: *orientation, cells });
+        }
+    }
+
+    matches
+}
+
+/// Part one as stencils: the word in a straight line or on a
+/// diagonal, in every direction
+pub fn word_stencils(word: &str) -> Vec<Stencil> {
+    vec![Stencil::line(word), Stencil::diagonal(word)]
+}
+
+/// Part two as a stencil: two `MAS` crossing on the `A`
+pub fn mas_cross_stencil() -> Stencil {
+    Stencil::parse("M.S/.A./M.S").unwrap()
+}
@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::{correct_update, find_violations};
+
+/// Edges of the rule graph as sorted `(before, after)` pairs, so
+/// the output doesn't change from run to run.
+fn sorted_edges(tree: &HashMap<i32, HashSet<i32>>) -> Vec<(i32, i32)> {
+    let mut edges = tree
+        .iter()
+        .flat_map(|(&before, afters)| afters.iter().map(move |&after| (before, after)))
+        .collect::<Vec<_>>();
+    edges.sort();
+    edges
+}
+
+/// Writes the whole rule graph in Graphviz DOT format. Every
+/// rule `a|b` becomes the edge `a -> b`.
+pub fn write_rule_graph<W: Write>(out: &mut W, tree: &HashMap<i32, HashSet<i32>>) -> std::io::Result<()> {
+    writeln!(out, "digraph rules {{")?;
+    for (before, after) in sorted_edges(tree) {
+        writeln!(out, "    {} -> {};", before, after)?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Writes the subgraph induced by the pages of `update`, i.e. only
+/// the rules where both pages are in the update.
+///
+/// With `highlight` set, the rules the update breaks are drawn in
+/// red, and every page is labelled with its position in the
+/// corrected order, which is also drawn as a dashed blue chain.
+pub fn write_update_graph<W: Write>(
+    out: &mut W,
+    tree: &HashMap<i32, HashSet<i32>>,
+    update: &[i32],
+    highlight: bool,
+) -> std::io::Result<()> {
+    let pages = update.iter().copied().collect::<HashSet<_>>();
+
+    writeln!(out, "digraph update {{")?;
+
+    // Only worked out when needed, as it never finishes if the
+    // update's pages have a cycle of rules. Callers asking for the
+    // highlight must rule that out with `check_acyclic` first.
+    let corrected = highlight.then(|| correct_update(update, tree));
+    for &page in update {
+        if let Some(corrected) = &corrected {
+            let position = corrected.iter().position(|&p| p == page).unwrap();
+            writeln!(out, "    {} [label=\"{}\\n#{}\"];", page, page, position)?;
+        } else {
+            writeln!(out, "    {};", page)?;
+        }
+    }
+
+    let broken = find_violations(update, tree)
+        .into_iter()
+        .map(|v| (v.later, v.earlier))
+        .collect::<HashSet<_>>();
+
+    for (before, after) in sorted_edges(tree)
+        .into_iter()
+        .filter(|(b, a)| pages.contains(b) && pages.contains(a))
+    {
+        if highlight && broken.contains(&(before, after)) {
+            writeln!(out, "    {} -> {} [color=red, penwidth=2];", before, after)?;
+        } else {
+            writeln!(out, "    {} -> {};", before, after)?;
+        }
+    }
+
+    if let Some(corrected) = &corrected {
+        for pair in corrected.windows(2) {
+            writeln!(
+                out,
+                "    {} -> {} [style=dashed, color=blue, constraint=false];",
+                pair[0], pair[1]
+            )?;
+        }
+    }
+
+    writeln!(out, "}}")
+}
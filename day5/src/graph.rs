@@ -0,0 +1,220 @@
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+
+use crate::OrderTree;
+
+/// The page ordering rules as a directed graph, where the rule
+/// `a|b` is the edge `a -> b`.
+///
+/// Note that the full puzzle rule set has cycles, so questions
+/// asked of the whole graph (`must_precede`) tend to answer yes
+/// for everything. The rules only form a proper order once they
+/// are restricted to the pages of an update, which is what the
+/// `*_within` and per-update queries do.
+#[derive(Debug)]
+pub struct RuleGraph {
+    rules: OrderTree,
+
+    /// Every page reachable from each page, built on first use
+    closure: OnceCell<OrderTree>,
+}
+
+/// Pages reachable from `start` following the edges in `rules`,
+/// only stepping on pages accepted by `keep`.
+fn reachable(rules: &OrderTree, start: i32, keep: impl Fn(i32) -> bool) -> HashSet<i32> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+    while let Some(page) = stack.pop() {
+        for &next in rules.get(&page).into_iter().flatten() {
+            if keep(next) && seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+
+    seen
+}
+
+impl RuleGraph {
+    pub fn new(rules: OrderTree) -> Self {
+        RuleGraph {
+            rules,
+            closure: OnceCell::new(),
+        }
+    }
+
+    /// The direct rules, as built by `construct_order_tree`
+    pub fn rules(&self) -> &OrderTree {
+        &self.rules
+    }
+
+    /// Transitive closure of the whole rule graph
+    fn closure(&self) -> &OrderTree {
+        self.closure.get_or_init(|| {
+            self.rules
+                .keys()
+                .map(|&page| (page, reachable(&self.rules, page, |_| true)))
+                .collect()
+        })
+    }
+
+    /// Whether some chain of rules forces `a` before `b`
+    pub fn must_precede(&self, a: i32, b: i32) -> bool {
+        self.closure().get(&a).is_some_and(|after| after.contains(&b))
+    }
+
+    /// Transitive closure of the rules between the pages of `update`
+    fn update_closure(&self, update: &[i32]) -> OrderTree {
+        let pages = update.iter().copied().collect::<HashSet<_>>();
+        update
+            .iter()
+            .map(|&page| (page, reachable(&self.rules, page, |p| pages.contains(&p))))
+            .collect()
+    }
+
+    /// Whether a chain of rules between pages of `update` forces
+    /// `a` before `b`
+    pub fn must_precede_within(&self, update: &[i32], a: i32, b: i32) -> bool {
+        self.update_closure(update)
+            .get(&a)
+            .is_some_and(|after| after.contains(&b))
+    }
+
+    /// Pairs of pages in `update` that the rules leave unordered.
+    /// If there are any, the corrected order isn't the only valid one.
+    pub fn unordered_pairs(&self, update: &[i32]) -> Vec<(i32, i32)> {
+        let closure = self.update_closure(update);
+        let precedes = |a: i32, b: i32| closure.get(&a).is_some_and(|after| after.contains(&b));
+
+        let mut pairs = vec![];
+        for i in 0..update.len() {
+            for &b in &update[i + 1..] {
+                let a = update[i];
+                if !precedes(a, b) && !precedes(b, a) {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        pairs
+    }
+
+    /// Every ordering of `update` that follows the rules. This grows
+    /// factorially with the number of unordered pages, so keep it to
+    /// small updates.
+    pub fn valid_orderings(&self, update: &[i32]) -> Vec<Vec<i32>> {
+        let pages = update.iter().copied().collect::<HashSet<_>>();
+
+        // Number of pages in the update that must come before each page
+        let mut blockers = update.iter().map(|&p| (p, 0)).collect::<HashMap<_, _>>();
+        for &page in update {
+            for after in self.rules.get(&page).into_iter().flatten() {
+                if pages.contains(after) {
+                    *blockers.get_mut(after).unwrap() += 1;
+                }
+            }
+        }
+
+        let mut orderings = vec![];
+        let mut current = vec![];
+        self.extend_orderings(update, &mut blockers, &mut current, &mut orderings);
+        orderings
+    }
+
+    /// Backtracks over every page that is free to go next
+    fn extend_orderings(
+        &self,
+        update: &[i32],
+        blockers: &mut HashMap<i32, usize>,
+        current: &mut Vec<i32>,
+        orderings: &mut Vec<Vec<i32>>,
+    ) {
+        if current.len() == update.len() {
+            orderings.push(current.clone());
+            return;
+        }
+
+        for &page in update {
+            if blockers[&page] != 0 || current.contains(&page) {
+                continue;
+            }
+
+            let afters = self
+                .rules
+                .get(&page)
+                .into_iter()
+                .flatten()
+                .filter(|a| blockers.contains_key(a))
+                .copied()
+                .collect::<Vec<_>>();
+
+            for after in &afters {
+                *blockers.get_mut(after).unwrap() -= 1;
+            }
+            current.push(page);
+
+            self.extend_orderings(update, blockers, current, orderings);
+
+            current.pop();
+            for after in &afters {
+                *blockers.get_mut(after).unwrap() += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{correct_update, parse_input, rules_to_tree};
+
+    fn example() -> (RuleGraph, Vec<Vec<i32>>) {
+        let (tree, updates) = parse_input(include_str!("test.in")).unwrap();
+        (RuleGraph::new(tree), updates)
+    }
+
+    #[test]
+    fn example_precedence() {
+        let (graph, _) = example();
+        assert!(graph.must_precede(97, 13));
+        assert!(graph.must_precede(75, 53));
+        assert!(!graph.must_precede(13, 97));
+        assert!(!graph.must_precede(97, 97));
+        assert!(!graph.must_precede(97, 1));
+
+        assert!(graph.must_precede_within(&[75, 47, 61, 53, 29], 75, 29));
+        assert!(!graph.must_precede_within(&[75, 47, 61, 53, 29], 29, 75));
+    }
+
+    #[test]
+    fn precedence_within_ignores_other_pages() {
+        let graph = RuleGraph::new(rules_to_tree(vec![(1, 2), (2, 3)]));
+        assert!(graph.must_precede(1, 3));
+        assert!(graph.must_precede_within(&[1, 2, 3], 1, 3));
+        assert!(!graph.must_precede_within(&[1, 3], 1, 3));
+    }
+
+    #[test]
+    fn example_updates_have_one_ordering() {
+        let (graph, updates) = example();
+        for update in &updates {
+            assert!(graph.unordered_pairs(update).is_empty());
+            assert_eq!(graph.valid_orderings(update), [correct_update(update, graph.rules())]);
+        }
+    }
+
+    #[test]
+    fn unordered_pages() {
+        // 1 comes first, but 2 and 3 can go either way round
+        let graph = RuleGraph::new(rules_to_tree(vec![(1, 2), (1, 3)]));
+        assert_eq!(graph.unordered_pairs(&[3, 2, 1]), [(3, 2)]);
+
+        let mut orderings = graph.valid_orderings(&[3, 2, 1]);
+        orderings.sort();
+        assert_eq!(orderings, [[1, 2, 3], [1, 3, 2]]);
+
+        // No rules between them at all, so any order will do
+        assert_eq!(graph.unordered_pairs(&[4, 5, 6]).len(), 3);
+        assert_eq!(graph.valid_orderings(&[4, 5, 6]).len(), 6);
+    }
+}
@@ -1,12 +1,79 @@
 use std::collections::{HashMap, HashSet};
 
-fn construct_order_tree(input: &str) -> HashMap<i32, HashSet<i32>> {
+use anyhow::{bail, Context};
+
+mod dot;
+mod graph;
+
+use graph::RuleGraph;
+
+/// Page ordering rules, mapping each page to the pages that
+/// must come after it.
+type OrderTree = HashMap<i32, HashSet<i32>>;
+
+/// Part of a day5 input a line belongs to, used to point
+/// parse errors at the right place.
+#[derive(Clone, Copy, Debug)]
+enum Section {
+    Rules,
+    Updates,
+}
+
+impl std::fmt::Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Section::Rules => write!(f, "rules"),
+            Section::Updates => write!(f, "updates"),
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> anyhow::Result<(i32, i32)> {
+    let (before, after) = line
+        .split_once('|')
+        .with_context(|| format!("expected `before|after`, got `{}`", line))?;
+
+    let parse = |page: &str| {
+        page.trim()
+            .parse::<i32>()
+            .with_context(|| format!("invalid page number `{}`", page))
+    };
+    Ok((parse(before)?, parse(after)?))
+}
+
+fn parse_update(line: &str) -> anyhow::Result<Vec<i32>> {
+    let update = line
+        .split(',')
+        .map(|page| {
+            page.trim()
+                .parse::<i32>()
+                .with_context(|| format!("invalid page number `{}`", page))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if update.len() % 2 == 0 {
+        bail!("update has {} pages, so no middle page", update.len())
+    }
+    Ok(update)
+}
+
+/// Parses each non-blank `(line number, line)` with `parse`, tagging
+/// any error with the section and (1-based) line number.
+fn parse_lines<'a, T>(
+    lines: impl Iterator<Item = (usize, &'a str)>,
+    section: Section,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<Vec<T>> {
+    lines
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, l)| parse(l).with_context(|| format!("{} section, line {}", section, n + 1)))
+        .collect()
+}
+
+fn rules_to_tree(rules: Vec<(i32, i32)>) -> OrderTree {
 
     let mut tree = HashMap::<i32, HashSet<i32>>::new();
-    for (before, after) in input
-        .lines()
-        .map(|l| l.split('|').collect::<Vec<_>>())
-        .map(|split| (split[0].parse::<i32>().unwrap(), split[1].parse::<i32>().unwrap())) {
+    for (before, after) in rules {
 
         match tree.get_mut(&before) {
             Some(depedencies) => {
@@ -21,38 +88,130 @@ fn construct_order_tree(input: &str) -> HashMap<i32, HashSet<i32>> {
     tree
 }
 
-fn construct_updates(input: &str) -> Vec<Vec<i32>> {
-    input
-        .lines()
-        .map(|l| l.split(',').collect::<Vec<_>>())
-        .map(|d| d.into_iter().map(|v| v.parse::<i32>().unwrap()))
-        .map(|d| d.collect::<Vec<_>>())
-        .collect::<Vec<_>>()
+fn construct_order_tree(input: &str) -> anyhow::Result<OrderTree> {
+    let rules = parse_lines(input.lines().enumerate(), Section::Rules, parse_rule)?;
+    Ok(rules_to_tree(rules))
+}
+
+fn construct_updates(input: &str) -> anyhow::Result<Vec<Vec<i32>>> {
+    parse_lines(input.lines().enumerate(), Section::Updates, parse_update)
+}
+
+/// Parses the puzzle's single-file format: the rules, a blank
+/// line, then the updates. Line numbers in errors are relative
+/// to the whole file.
+fn parse_input(input: &str) -> anyhow::Result<(OrderTree, Vec<Vec<i32>>)> {
+    let lines = input.lines().enumerate().collect::<Vec<_>>();
+    let Some(separator) = lines.iter().position(|(_, l)| l.trim().is_empty()) else {
+        // Without the blank line the updates read as rules, so point
+        // at the first of them if there is one
+        parse_lines(lines.iter().copied(), Section::Rules, parse_rule)
+            .context("missing blank line between the rules and the updates")?;
+        bail!("missing blank line between the rules and the updates")
+    };
+
+    let (rule_lines, update_lines) = lines.split_at(separator);
+    let rules = parse_lines(rule_lines.iter().copied(), Section::Rules, parse_rule)?;
+    let updates = parse_lines(update_lines.iter().copied(), Section::Updates, parse_update)?;
+
+    Ok((rules_to_tree(rules), updates))
+}
+
+/// A rule broken by an update. The rule `later|earlier` says `later`
+/// must be printed before `earlier`, but the update has them the
+/// other way round.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Violation {
+    /// Index of the page that appears first in the update
+    earlier_index: usize,
+    earlier: i32,
+
+    /// Index of the page that should have come first
+    later_index: usize,
+    later: i32,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (index {}) before {} (index {}) breaks rule {}|{}",
+            self.earlier, self.earlier_index, self.later, self.later_index, self.later, self.earlier
+        )
+    }
+}
+
+/// Walks every pair of pages in `updates` and yields the ones
+/// that go against a rule in `dependencies`.
+fn violations<'a>(
+    updates: &'a [i32],
+    dependencies: &'a HashMap<i32, HashSet<i32>>,
+) -> impl Iterator<Item = Violation> + 'a {
+    (0..updates.len())
+        .flat_map(move |i| (i + 1..updates.len()).map(move |j| (i, j)))
+        .filter(move |&(i, j)| {
+            // Check current items comes after each prev item in their lists
+            dependencies
+                .get(&updates[j])
+                .is_some_and(|deps| deps.contains(&updates[i]))
+        })
+        .map(move |(i, j)| Violation {
+            earlier_index: i,
+            earlier: updates[i],
+            later_index: j,
+            later: updates[j],
+        })
+}
+
+/// Same as `check_update`, but returns every broken rule rather than
+/// stopping at the first one.
+fn find_violations(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> Vec<Violation> {
+    violations(updates, dependencies).collect()
 }
 
 fn check_update(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> bool {
+    violations(updates, dependencies).next().is_none()
+}
 
-    for i in 0..updates.len() {
-
-        let current = updates[i];
-
-        // Check current comes before all the following items in its list
-        // is this necessary?
-        // Check current items comes after each prev item in their lists
-        for j in i..updates.len() {
-            let after = updates[j];
-            let deps = dependencies
-                .get(&after)
-                .cloned()
-                .unwrap_or_default();
-            
-            if deps.contains(&current) {
-                return false;
+/// Fails if the rules between the pages of `updates` have a cycle,
+/// in which case there is no corrected order and `correct_update`
+/// would never finish.
+fn check_acyclic(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> anyhow::Result<()> {
+    let pages = updates.iter().copied().collect::<HashSet<_>>();
+    let afters = |page: i32| {
+        dependencies
+            .get(&page)
+            .into_iter()
+            .flatten()
+            .filter(|after| pages.contains(after))
+    };
+
+    // Number of pages that must come before each page, taking pages
+    // off the front until none are left or all are blocked
+    let mut blockers = pages.iter().map(|&p| (p, 0)).collect::<HashMap<_, _>>();
+    for &page in &pages {
+        for after in afters(page) {
+            *blockers.get_mut(after).unwrap() += 1;
+        }
+    }
+
+    let mut free = pages.iter().copied().filter(|p| blockers[p] == 0).collect::<Vec<_>>();
+    let mut placed = 0;
+    while let Some(page) = free.pop() {
+        placed += 1;
+        for after in afters(page) {
+            let count = blockers.get_mut(after).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                free.push(*after);
             }
         }
     }
 
-    true
+    if placed != pages.len() {
+        bail!("the rules between the pages of update {:?} have a cycle", updates)
+    }
+    Ok(())
 }
 
 // Same as check_update but it swaps the wrong values,
@@ -87,29 +246,212 @@ fn correct_update(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) ->
     new_updates
 }
 
+/// Where each page of `updates` ends up in the corrected order.
+fn corrected_positions(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> Vec<usize> {
+    let corrected = correct_update(updates, dependencies);
+    updates
+        .iter()
+        .map(|page| corrected.iter().position(|p| p == page).unwrap())
+        .collect()
+}
+
+/// Minimum number of adjacent swaps that turn `updates` into the
+/// corrected order, i.e. the number of pairs it has the wrong way
+/// round.
+fn count_min_swaps(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> usize {
+    let positions = corrected_positions(updates, dependencies);
+
+    let mut inversions = 0;
+    for i in 0..positions.len() {
+        inversions += positions[i + 1..]
+            .iter()
+            .filter(|&&p| p < positions[i])
+            .count();
+    }
+
+    inversions
+}
+
+/// Longest subsequence of `updates` that is already in corrected
+/// order. These pages can stay where they are.
+fn longest_consistent_subsequence(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> Vec<i32> {
+    let positions = corrected_positions(updates, dependencies);
+
+    // lengths[i] is the longest increasing run of positions ending
+    // at i, and previous[i] is where that run came from
+    let mut lengths = vec![1; positions.len()];
+    let mut previous = vec![None; positions.len()];
+    for i in 0..positions.len() {
+        for j in 0..i {
+            if positions[j] < positions[i] && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+
+    let mut current = (0..lengths.len()).max_by_key(|&i| lengths[i]);
+    let mut subsequence = vec![];
+    while let Some(i) = current {
+        subsequence.push(updates[i]);
+        current = previous[i];
+    }
+
+    subsequence.reverse();
+    subsequence
+}
+
+/// Minimum number of pages that must be moved elsewhere to turn
+/// `updates` into the corrected order.
+fn count_min_moves(updates: &[i32], dependencies: &HashMap<i32, HashSet<i32>>) -> usize {
+    updates.len() - longest_consistent_subsequence(updates, dependencies).len()
+}
+
 fn get_middle(input: &[i32]) -> i32 {
     assert!((input.len() % 2) == 1);
     input[input.len()/2]
 }
 
-fn main() {
+/// `day5 dot [--update <index>] [--highlight] [--output <file>]`
+///
+/// Writes the rule graph in DOT format to `<file>`, or stdout if no
+/// file is given. With `--update` only the pages of that update
+/// (0-based) are drawn.
+fn run_dot(args: &[String], tree: &HashMap<i32, HashSet<i32>>, updates: &[Vec<i32>]) -> anyhow::Result<()> {
+    let mut update = None;
+    let mut output = None;
+    let mut highlight = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--update" => {
+                let index = args
+                    .next()
+                    .context("--update needs an index")?
+                    .parse::<usize>()?;
+                update = Some(updates.get(index).context("update index out of range")?);
+            }
+            "--output" => {
+                output = Some(args.next().context("--output needs a file")?);
+            }
+            "--highlight" => {
+                highlight = true;
+            }
+            _ => bail!("unknown dot argument: {}", arg),
+        }
+    }
+
+    if highlight && update.is_none() {
+        bail!("--highlight only makes sense with --update");
+    }
+    if let Some(update) = update.filter(|_| highlight) {
+        check_acyclic(update, tree)?;
+    }
+
+    let mut out: Box<dyn std::io::Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+
+    match update {
+        Some(update) => dot::write_update_graph(&mut out, tree, update, highlight)?,
+        None => dot::write_rule_graph(&mut out, tree)?,
+    }
+
+    Ok(())
+}
 
-    let tree_input = include_str!("day5_tree.in");
-    let tree = construct_order_tree(tree_input);
-    let updates_input = include_str!("day5_update.in");
-    let updates = construct_updates(updates_input);
+/// `day5 query precede <a> <b> [--update <index>]`
+/// `day5 query unordered <index>`
+/// `day5 query orderings <index>`
+///
+/// Asks the rule graph whether `a` must come before `b` (within an
+/// update if one is given), which pages of an update the rules leave
+/// unordered, or lists every valid ordering of an update.
+fn run_query(args: &[String], graph: &RuleGraph, updates: &[Vec<i32>]) -> anyhow::Result<()> {
+    let update_at = |arg: Option<&String>| -> anyhow::Result<&Vec<i32>> {
+        let index = arg.context("missing update index")?.parse::<usize>()?;
+        updates.get(index).context("update index out of range")
+    };
+
+    match args.first().map(String::as_str) {
+        Some("precede") => {
+            let a = args.get(1).context("missing first page")?.parse::<i32>()?;
+            let b = args.get(2).context("missing second page")?.parse::<i32>()?;
+            let precedes = match args.get(3).map(String::as_str) {
+                Some("--update") => graph.must_precede_within(update_at(args.get(4))?, a, b),
+                Some(arg) => bail!("unknown query argument: {}", arg),
+                None => graph.must_precede(a, b),
+            };
+            println!("{} must precede {}: {}", a, b, precedes);
+        },
+        Some("unordered") => {
+            for (a, b) in graph.unordered_pairs(update_at(args.get(1))?) {
+                println!("{} {}", a, b);
+            }
+        },
+        Some("orderings") => {
+            for ordering in graph.valid_orderings(update_at(args.get(1))?) {
+                println!("{:?}", ordering);
+            }
+        },
+        _ => bail!("expected one of: precede, unordered, orderings"),
+    }
+
+    Ok(())
+}
+
+fn try_main() -> anyhow::Result<()> {
+
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    // `--input <file>` reads a single rules + updates file, otherwise
+    // we use the split inputs
+    let (tree, updates) = match args.iter().position(|a| a == "--input") {
+        Some(i) => {
+            let path = args.get(i + 1).context("--input needs a file")?;
+            let parsed = parse_input(&std::fs::read_to_string(path)?)?;
+            args.drain(i..i + 2);
+            parsed
+        },
+        None => {
+            let tree = construct_order_tree(include_str!("day5_tree.in"))?;
+            let updates = construct_updates(include_str!("day5_update.in"))?;
+            (tree, updates)
+        }
+    };
+
+    let graph = RuleGraph::new(tree);
+    let tree = graph.rules();
+
+    match args.first().map(String::as_str) {
+        Some("dot") => return run_dot(&args[1..], tree, &updates),
+        Some("query") => return run_query(&args[1..], &graph, &updates),
+        _ => {}
+    }
+
+    // Pass `--explain` to list the broken rules under each invalid update
+    let explain = args.iter().any(|a| a == "--explain");
+    // Pass `--rank` to list the invalid updates from most to least out of order
+    let rank = args.iter().any(|a| a == "--rank");
+
+    // Any update with a cycle between its pages breaks a rule, so
+    // only the invalid ones need checking before they get corrected
+    for update in updates.iter().filter(|u| !check_update(u, tree)) {
+        check_acyclic(update, tree)?;
+    }
 
     let valid_updates = updates
         .iter()
-        .filter(|u| check_update(u, &tree))
-        .map(|u| u.clone())
+        .filter(|u| check_update(u, tree))
+        .cloned()
         .collect::<Vec<_>>();
 
     let invalid_updates = updates
         .iter()
-        .filter(|u| !check_update(u, &tree))
-        .map(|u| u.clone())
-        .map(|u| correct_update(&u, &tree))
+        .filter(|u| !check_update(u, tree))
+        .map(|u| correct_update(u, tree))
         .collect::<Vec<_>>();
 
     let sum_mids: i32 = valid_updates
@@ -123,10 +465,204 @@ fn main() {
         .sum();
 
 
+    if explain {
+        for update in updates.iter().filter(|u| !check_update(u, tree)) {
+            println!("{:?}", update);
+            for violation in find_violations(update, tree) {
+                println!("    {}", violation);
+            }
+        }
+    }
+
+    // How far off the invalid updates were
+    let mut distances = updates
+        .iter()
+        .filter(|u| !check_update(u, tree))
+        .map(|u| (count_min_swaps(u, tree), count_min_moves(u, tree), u))
+        .collect::<Vec<_>>();
+    distances.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+
+    if rank {
+        for (swaps, moves, update) in &distances {
+            println!("{:?}: {} swaps, {} moves", update, swaps, moves);
+        }
+    }
+
     println!("Part one valid updates: {}", valid_updates.len());
     println!("Part one sum of mids: {}", sum_mids);
 
     println!("Part two invalid updates: {}", invalid_updates.len());
     println!("Part two sum of mids: {}", invalid_sum_mids);
 
+    let ambiguous_updates = updates
+        .iter()
+        .filter(|u| !graph.unordered_pairs(u).is_empty())
+        .count();
+    println!("Part two ambiguous updates: {}", ambiguous_updates);
+
+    println!(
+        "Part two swaps needed: {}",
+        distances.iter().map(|d| d.0).sum::<usize>()
+    );
+    println!(
+        "Part two moves needed: {}",
+        distances.iter().map(|d| d.1).sum::<usize>()
+    );
+    if let Some((swaps, moves, update)) = distances.first() {
+        println!("Part two worst update: {:?} ({} swaps, {} moves)", update, swaps, moves);
+    }
+
+    Ok(())
+}
+
+fn main() {
+    match try_main() {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("finished with error: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    fn error_text(result: anyhow::Result<impl std::fmt::Debug>) -> String {
+        format!("{:#}", result.unwrap_err())
+    }
+
+    /// Both parts' sums of middle pages
+    fn answers(tree: &OrderTree, updates: &[Vec<i32>]) -> (i32, i32) {
+        let (valid, invalid): (Vec<_>, Vec<_>) = updates.iter().partition(|u| check_update(u, tree));
+        (
+            valid.iter().map(|u| get_middle(u)).sum(),
+            invalid.iter().map(|u| get_middle(&correct_update(u, tree))).sum(),
+        )
+    }
+
+    #[test]
+    fn example() {
+        let (tree, updates) = parse_input(include_str!("test.in")).unwrap();
+        assert_eq!(updates.len(), 6);
+        assert_eq!(answers(&tree, &updates), (143, 123));
+    }
+
+    #[test]
+    fn single_file_matches_split_files() {
+        let (tree, updates) = parse_input(include_str!("test.in")).unwrap();
+        assert_eq!(tree, construct_order_tree(include_str!("test_tree.in")).unwrap());
+        assert_eq!(updates, construct_updates(include_str!("test_update.in")).unwrap());
+    }
+
+    #[test]
+    fn parse_errors_name_the_section_and_line() {
+        let error = error_text(parse_input("1|2\n1-3\n\n1,2,3\n"));
+        assert!(error.contains("rules section, line 2"), "{}", error);
+
+        let error = error_text(parse_input("1|2\n\n1,2,3\n1,x,3\n"));
+        assert!(error.contains("updates section, line 4"), "{}", error);
+        assert!(error.contains("invalid page number `x`"), "{}", error);
+
+        let error = error_text(parse_input("1|2\n2|3\n1,2,3\n"));
+        assert!(error.contains("missing blank line"), "{}", error);
+        assert!(error.contains("rules section, line 3"), "{}", error);
+
+        let error = error_text(parse_input("1|2\n2|3\n"));
+        assert!(error.contains("missing blank line"), "{}", error);
+    }
+
+    #[test]
+    fn example_violations() {
+        let (tree, _) = parse_input(include_str!("test.in")).unwrap();
+        let violation = |earlier_index, earlier, later_index, later| Violation {
+            earlier_index,
+            earlier,
+            later_index,
+            later,
+        };
+
+        assert_eq!(
+            find_violations(&[97, 13, 75, 29, 47], &tree),
+            [
+                violation(1, 13, 2, 75),
+                violation(1, 13, 3, 29),
+                violation(1, 13, 4, 47),
+                violation(3, 29, 4, 47),
+            ]
+        );
+        assert_eq!(
+            violation(1, 13, 2, 75).to_string(),
+            "13 (index 1) before 75 (index 2) breaks rule 75|13"
+        );
+        assert!(find_violations(&[75, 47, 61, 53, 29], &tree).is_empty());
+    }
+
+    #[test]
+    fn even_updates_are_an_error() {
+        let error = error_text(parse_input("1|2\n\n1,2\n"));
+        assert!(error.contains("updates section, line 3"), "{}", error);
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        let (tree, _) = parse_input("1|2\n2|3\n3|1\n\n1,2,3\n").unwrap();
+        assert!(check_acyclic(&[1, 2, 3], &tree).is_err());
+        assert!(check_acyclic(&[1, 2, 4], &tree).is_ok());
+
+        let (tree, updates) = parse_input(include_str!("test.in")).unwrap();
+        for update in &updates {
+            assert!(check_acyclic(update, &tree).is_ok());
+        }
+    }
+
+    #[test]
+    fn example_distances() {
+        let (tree, _) = parse_input(include_str!("test.in")).unwrap();
+
+        let update = [97, 13, 75, 29, 47];
+        assert_eq!(count_min_swaps(&update, &tree), 4);
+        assert_eq!(longest_consistent_subsequence(&update, &tree).len(), 3);
+        assert_eq!(count_min_moves(&update, &tree), 2);
+
+        let update = [75, 97, 47, 61, 53];
+        assert_eq!(count_min_swaps(&update, &tree), 1);
+        assert_eq!(longest_consistent_subsequence(&update, &tree), [75, 47, 61, 53]);
+        assert_eq!(count_min_moves(&update, &tree), 1);
+    }
+
+    #[test]
+    fn example_swaps_match_violations() {
+        // The example rules order every pair of pages in each update
+        let (tree, updates) = parse_input(include_str!("test.in")).unwrap();
+        for update in &updates {
+            assert_eq!(count_min_swaps(update, &tree), find_violations(update, &tree).len());
+        }
+    }
+
+    #[quickcheck]
+    fn swaps_match_violations_for_total_orders(pages: Vec<u8>) -> bool {
+        // Distinct pages in any order, with rules putting them in
+        // increasing order
+        let mut update = vec![];
+        for page in pages.into_iter().take(16).map(i32::from) {
+            if !update.contains(&page) {
+                update.push(page);
+            }
+        }
+
+        let mut sorted = update.clone();
+        sorted.sort();
+        let mut rules = vec![];
+        for (i, &before) in sorted.iter().enumerate() {
+            for &after in &sorted[i + 1..] {
+                rules.push((before, after));
+            }
+        }
+        let tree = rules_to_tree(rules);
+
+        correct_update(&update, &tree) == sorted
+            && count_min_swaps(&update, &tree) == find_violations(&update, &tree).len()
+    }
 }
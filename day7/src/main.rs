@@ -26,8 +26,8 @@ fn get_operator_cartesian(dims: usize) -> Vec<Vec<Operators>> {
         .collect::<Vec<_>>()
 }
 
-fn apply_formula(operands: &Vec<i64>, operations: &Vec<Operators>) -> i64 {
-    assert!(operands.len() > 0);
+fn apply_formula(operands: &[i64], operations: &[Operators]) -> i64 {
+    assert!(!operands.is_empty());
     assert!(
         operands.len() == operations.len() + 1,
         "mismatch in operand size"
@@ -60,7 +60,7 @@ fn apply_formula(operands: &Vec<i64>, operations: &Vec<Operators>) -> i64 {
 }
 
 fn find_solutions(test: &Test) -> anyhow::Result<Vec<Vec<Operators>>> {
-    if test.operands.len() == 0 {
+    if test.operands.is_empty() {
         bail!("invalid number of operands")
     }
 
@@ -106,7 +106,7 @@ fn parse_test_line(test_line: &str) -> anyhow::Result<Test> {
 fn parse_input(input: &str) -> anyhow::Result<Vec<Test>> {
     let lines = input
         .lines()
-        .map(|l| parse_test_line(l))
+        .map(parse_test_line)
         .collect::<Vec<_>>();
 
     if !lines.iter().all(|test| test.is_ok()) {
@@ -125,7 +125,7 @@ fn try_main() -> anyhow::Result<()> {
 
     let valid_tests = tests
         .iter()
-        .filter(|t| find_solutions(&t).is_ok())
+        .filter(|t| find_solutions(t).is_ok())
         .collect::<Vec<_>>();
 
     println!(